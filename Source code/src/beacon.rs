@@ -0,0 +1,99 @@
+//! Beacon mode: the unit sends a message on its own, over and over.
+//!
+//! Every transmission is the message memory chosen in the settings (with its
+//! macros expanded, so a `{NR}` counts up), then, if enabled, DE and the
+//! callsign, then a steady carrier for fox hunts to take a bearing on. The
+//! screen counts down to the next transmission, and '*' stops the beacon at
+//! any time. The repeat and loop options of the memory are not used, the
+//! beacon has its own interval.
+
+use core::fmt::Write;
+
+use embassy_futures::select::{select, Either};
+use embassy_time::{Duration, Instant, Timer};
+use heapless::String;
+
+use crate::display::Display;
+use crate::keyer::Keyer;
+use crate::keypad::Keypad;
+use crate::macros;
+use crate::memories;
+use crate::settings;
+use crate::storage::Storage;
+use crate::ui::{show_lines, wait_key};
+
+// Sent after the message when the CW ID is on
+const ID: &str = " DE {CALL}";
+
+// Count `seconds` down on the bottom line, false if the user stopped the
+// beacon with '*'
+async fn countdown<D: Display>(lcd: &mut D, keypad: &mut Keypad, top: &str, seconds: u16) -> bool {
+    // Fixed deadlines, so that the keys pressed meanwhile do not stretch the period
+    let mut next = Instant::now();
+
+    for left in (1..=seconds).rev() {
+        let mut bottom = String::<16>::new();
+        write!(bottom, "{}:{:02} *=stop", left / 60, left % 60).ok();
+        show_lines(lcd, top, &bottom);
+
+        next += Duration::from_secs(1);
+        loop {
+            match select(wait_key(keypad), Timer::at(next)).await {
+                Either::First('*') => return false,
+                Either::First(_) => {}
+                Either::Second(_) => break,
+            }
+        }
+    }
+    true
+}
+
+// Run the beacon until the user stops it with '*'
+pub async fn run<D: Display>(lcd: &mut D, keypad: &mut Keypad, keyer: &mut Keyer, flash: &mut Storage) {
+    let mut transmissions = 0u32;
+
+    loop {
+        let settings = settings::get();
+        let index = settings.beacon_memory as usize;
+        let memory = memories::load(flash, index);
+
+        let mut top = String::<16>::new();
+        write!(top, "Beacon M{}", index + 1).ok();
+
+        if memory.is_empty() {
+            show_lines(lcd, &top, "Memory is empty");
+            wait_key(keypad).await;
+            return;
+        }
+
+        if !macros::send(lcd, keypad, keyer, flash, &memory.text).await {
+            break;
+        }
+        if settings.beacon_id && !settings.callsign.is_empty() && !macros::send(lcd, keypad, keyer, flash, ID).await {
+            break;
+        }
+
+        if settings.beacon_carrier_s > 0 {
+            let mut tone = top.clone();
+            tone.push_str(" tone").ok();
+
+            keyer.sidetone(true).await;
+            let done = countdown(lcd, keypad, &tone, settings.beacon_carrier_s as u16).await;
+            keyer.sidetone(false).await;
+            if !done {
+                break;
+            }
+        }
+
+        transmissions += 1;
+        defmt::info!("Beacon transmission {} sent", transmissions);
+
+        write!(top, " #{}", transmissions).ok();
+        if !countdown(lcd, keypad, &top, settings.beacon_interval()).await {
+            break;
+        }
+    }
+
+    show_lines(lcd, "Beacon stopped", "");
+    Timer::after(Duration::from_millis(1000)).await;
+}
@@ -0,0 +1,155 @@
+//! Interactive browser for the Morse table.
+//!
+//! '2' and '8' step up and down through the table, '5' plays the entry shown,
+//! '#' cycles through the filters and '*' goes back to the main loop.
+
+use core::fmt::Write;
+
+use embassy_time::{Duration, Timer};
+use heapless::String;
+
+use crate::display::Display;
+use crate::keyer::Keyer;
+use crate::keypad::Keypad;
+use crate::morse::{MORSE_TABLE, mnemonic, prosign};
+use crate::ui::{show_lines, wait_key_repeating, write_code};
+
+fn longest_code() -> usize {
+    MORSE_TABLE.iter().map(|(_, code)| code.len()).max().unwrap_or(0)
+}
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum Filter {
+    All,
+    Length(usize),
+    StartsWith(char),
+}
+
+impl Filter {
+    fn matches(self, code: &str) -> bool {
+        match self {
+            Filter::All => true,
+            Filter::Length(len) => code.len() == len,
+            Filter::StartsWith(element) => code.starts_with(element),
+        }
+    }
+
+    fn next(self) -> Self {
+        match self {
+            Filter::All => Filter::Length(1),
+            Filter::Length(len) if len < longest_code() => Filter::Length(len + 1),
+            Filter::Length(_) => Filter::StartsWith('.'),
+            Filter::StartsWith('.') => Filter::StartsWith('-'),
+            Filter::StartsWith(_) => Filter::All,
+        }
+    }
+}
+
+pub struct Browser {
+    index: usize,
+    filter: Filter,
+}
+
+impl Browser {
+    pub fn new() -> Self {
+        Self { index: 0, filter: Filter::All }
+    }
+
+    pub fn current(&self) -> (char, &'static str) {
+        MORSE_TABLE[self.index]
+    }
+
+    // Position of the current entry among the ones the filter lets through
+    pub fn position(&self) -> (usize, usize) {
+        let visible = MORSE_TABLE.iter().filter(|(_, code)| self.filter.matches(code));
+        let before = MORSE_TABLE[..self.index]
+            .iter()
+            .filter(|(_, code)| self.filter.matches(code))
+            .count();
+
+        (before + 1, visible.count())
+    }
+
+    // Step to the next (or previous) entry that matches the filter, wrapping around
+    pub fn step(&mut self, forward: bool) {
+        let len = MORSE_TABLE.len();
+
+        for offset in 1..=len {
+            let index = if forward {
+                (self.index + offset) % len
+            } else {
+                (self.index + len - offset) % len
+            };
+
+            if self.filter.matches(MORSE_TABLE[index].1) {
+                self.index = index;
+                return;
+            }
+        }
+    }
+
+    pub fn next_filter(&mut self) {
+        self.filter = self.filter.next();
+
+        if !self.filter.matches(self.current().1) {
+            self.step(true);
+        }
+    }
+}
+
+fn show_entry<D: Display>(lcd: &mut D, browser: &Browser) {
+    let (ch, _) = browser.current();
+    let (position, count) = browser.position();
+
+    let mut bottom = String::<16>::new();
+    match prosign(ch) {
+        Some(name) => write!(bottom, "Prosign {}", name).ok(),
+        None => bottom.push_str(mnemonic(ch).unwrap_or("")).ok(),
+    };
+
+    show_lines(lcd, "", &bottom);
+
+    // The character and its code on the left, the counter on the right
+    lcd.set_cursor_pos((0, 0));
+    lcd.write_char(ch);
+    lcd.write_char(' ');
+    write_code(lcd, ch);
+
+    let mut counter = String::<8>::new();
+    write!(counter, "{}/{}", position, count).ok();
+    lcd.set_cursor_pos((lcd.columns() - counter.len() as u8, 0));
+    lcd.write_str(&counter);
+}
+
+fn show_filter<D: Display>(lcd: &mut D, filter: Filter) {
+    let mut bottom = String::<16>::new();
+    match filter {
+        Filter::All => write!(bottom, "All characters").ok(),
+        Filter::Length(len) => write!(bottom, "Length {}", len).ok(),
+        Filter::StartsWith(element) => write!(bottom, "Starts with {}", element).ok(),
+    };
+
+    show_lines(lcd, "Filter:", &bottom);
+}
+
+// Browse the table until the user goes back with '*'
+pub async fn run<D: Display>(lcd: &mut D, keypad: &mut Keypad, keyer: &mut Keyer) {
+    let mut browser = Browser::new();
+
+    loop {
+        show_entry(lcd, &browser);
+
+        match wait_key_repeating(keypad, &['2', '8']).await {
+            '2' => browser.step(false),
+            '8' => browser.step(true),
+            '5' => keyer.play_char(browser.current().0).await,
+            '#' => {
+                browser.next_filter();
+                show_filter(lcd, browser.filter);
+                Timer::after(Duration::from_millis(800)).await;
+            }
+            '*' => return,
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,179 @@
+//! The screen, behind a trait so the UI does not depend on one module.
+//!
+//! Every screen is a grid of character cells with a cursor, which is all the
+//! UI needs. Screens that can draw more than text also hand out a `Graphics`
+//! surface, and every screen can show the few glyphs Morse needs (see
+//! `Glyph`), in the best way it can. The backends are the HD44780 character
+//! LCDs over I2C (`CharLcd`, 16x2 or 20x4) and the SPI TFTs (`tft::Tft`); a
+//! unit is built for its screen with the cargo features `lcd2004`,
+//! `tft-st7789` or `tft-ili9341`, and the 16x2 LCD otherwise.
+
+use embedded_graphics::pixelcolor::Rgb565;
+use embedded_graphics::primitives::Rectangle;
+use lcd1602_driver::command::State;
+use lcd1602_driver::lcd::{Basic, CGRAMGraph, Ext};
+
+// Text grid of the character LCD the unit was built with
+#[cfg(feature = "lcd2004")]
+pub const LCD_SIZE: (u8, u8) = (20, 4);
+#[cfg(not(feature = "lcd2004"))]
+pub const LCD_SIZE: (u8, u8) = (16, 2);
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum Cursor {
+    Hidden,
+    // A steady underline under the next cell
+    Underline,
+    // A blinking block, for text not confirmed yet
+    Blink,
+}
+
+// Symbols beyond ASCII
+#[derive(Copy, Clone, PartialEq)]
+pub enum Glyph {
+    Dot,
+    Dash,
+    // Under the bar that joins the letters of a prosign
+    OverDot,
+    OverDash,
+    // A progress bar cell with 0 to 5 fifths filled
+    Bar(u8),
+}
+
+pub trait Display {
+    // Size of the text grid, in cells
+    fn columns(&self) -> u8;
+    fn rows(&self) -> u8;
+
+    fn clear(&mut self);
+
+    // Move the cursor to a (column, row) cell, where the next character goes
+    fn set_cursor_pos(&mut self, pos: (u8, u8));
+
+    // Write at the cursor and move it one cell right
+    fn write_char(&mut self, c: char);
+
+    fn write_str(&mut self, s: &str) {
+        for c in s.chars() {
+            self.write_char(c);
+        }
+    }
+
+    // Write a glyph at the cursor and move it one cell right; plain text
+    // stands in for the glyphs on screens that cannot draw them
+    fn write_glyph(&mut self, glyph: Glyph) {
+        self.write_char(match glyph {
+            Glyph::Dot | Glyph::OverDot => '.',
+            Glyph::Dash | Glyph::OverDash => '-',
+            Glyph::Bar(0..=2) => ' ',
+            Glyph::Bar(_) => '#',
+        });
+    }
+
+    fn set_cursor(&mut self, cursor: Cursor);
+
+    // Drawing beyond the text grid, None on character-only screens
+    fn graphics(&mut self) -> Option<&mut dyn Graphics> {
+        None
+    }
+}
+
+pub trait Graphics {
+    // Size of the surface, in pixels
+    fn size(&self) -> (u32, u32);
+
+    fn fill_rect(&mut self, area: Rectangle, color: Rgb565);
+}
+
+// The glyphs in the 8 CGRAM slots of the HD44780, 5x8 pixels each, in the
+// order of their character codes
+const CGRAM_GLYPHS: [[u8; 8]; 8] = [
+    // Dot and dash
+    [0b00000, 0b00000, 0b00000, 0b01110, 0b01110, 0b00000, 0b00000, 0b00000],
+    [0b00000, 0b00000, 0b00000, 0b11111, 0b11111, 0b00000, 0b00000, 0b00000],
+    // The same under an overbar
+    [0b11111, 0b00000, 0b00000, 0b01110, 0b01110, 0b00000, 0b00000, 0b00000],
+    [0b11111, 0b00000, 0b00000, 0b11111, 0b11111, 0b00000, 0b00000, 0b00000],
+    // Bar cells one to four fifths full
+    [0b10000; 8],
+    [0b11000; 8],
+    [0b11100; 8],
+    [0b11110; 8],
+];
+
+// Solid block of the character ROM, the full bar cell
+const FULL_BLOCK: u8 = 0xFF;
+
+// A HD44780 character LCD driven through lcd1602-driver. The driver only
+// knows two lines; on a 20x4 module the third and fourth lines continue the
+// first and second ones in the display memory.
+pub struct CharLcd<L> {
+    lcd: L,
+    columns: u8,
+    rows: u8,
+}
+
+impl<L: Basic + Ext> CharLcd<L> {
+    pub fn new(mut lcd: L, (columns, rows): (u8, u8)) -> Self {
+        for (index, graph) in CGRAM_GLYPHS.iter().enumerate() {
+            lcd.write_graph_to_cgram(index as u8, &CGRAMGraph { upper: *graph, lower: None });
+        }
+
+        // Back to the display memory after the CGRAM writes
+        lcd.return_home();
+        Self { lcd, columns, rows }
+    }
+}
+
+impl<L: Basic + Ext> Display for CharLcd<L> {
+    fn columns(&self) -> u8 {
+        self.columns
+    }
+
+    fn rows(&self) -> u8 {
+        self.rows
+    }
+
+    fn clear(&mut self) {
+        self.lcd.clean_display();
+    }
+
+    fn set_cursor_pos(&mut self, (col, row): (u8, u8)) {
+        if row < 2 {
+            self.lcd.set_cursor_pos((col, row));
+        } else {
+            self.lcd.set_cursor_pos((col + self.columns, row - 2));
+        }
+    }
+
+    fn write_char(&mut self, c: char) {
+        self.lcd.write_char_to_cur(c);
+    }
+
+    fn write_str(&mut self, s: &str) {
+        self.lcd.write_str_to_cur(s);
+    }
+
+    fn write_glyph(&mut self, glyph: Glyph) {
+        match glyph {
+            Glyph::Dot => self.lcd.write_graph_to_cur(0),
+            Glyph::Dash => self.lcd.write_graph_to_cur(1),
+            Glyph::OverDot => self.lcd.write_graph_to_cur(2),
+            Glyph::OverDash => self.lcd.write_graph_to_cur(3),
+            Glyph::Bar(0) => self.lcd.write_char_to_cur(' '),
+            Glyph::Bar(fifths @ 1..=4) => self.lcd.write_graph_to_cur(3 + fifths),
+            Glyph::Bar(_) => self.lcd.write_u8_to_cur(FULL_BLOCK),
+        }
+    }
+
+    fn set_cursor(&mut self, cursor: Cursor) {
+        let (on, blink) = match cursor {
+            Cursor::Hidden => (State::Off, State::Off),
+            Cursor::Underline => (State::On, State::Off),
+            Cursor::Blink => (State::On, State::On),
+        };
+
+        self.lcd.set_cursor_blink_state(blink);
+        self.lcd.set_cursor_state(on);
+    }
+}
@@ -0,0 +1,170 @@
+//! The message buffer and its editing operations.
+//!
+//! Characters are inserted at the cursor, which can be moved left and right.
+//! The top line of the screen shows a window of the buffer that follows the
+//! cursor, the bottom line the input mode in its left corner, then a status,
+//! and the fill level.
+
+use core::fmt::Write;
+
+use heapless::{String, Vec};
+
+use crate::display::{Cursor, Display, Glyph};
+use crate::ui::write_code;
+
+pub const CAPACITY: usize = 32;
+
+// Columns kept for the input mode indicator on the bottom line
+const INDICATOR_WIDTH: u8 = 4;
+
+// Cells of the bar counting down to the confirmation of a multitap character
+pub const COUNTDOWN_CELLS: usize = 8;
+
+pub struct Full;
+
+pub struct Editor {
+    chars: Vec<char, CAPACITY>,
+    // Insertion point, between 0 and the length of the buffer
+    cursor: usize,
+    // First character shown on the LCD
+    scroll: usize,
+    // Column of the cursor while pending text is shown
+    pending_col: u8,
+    // Input mode shown in the bottom left corner
+    indicator: &'static str,
+}
+
+impl Editor {
+    pub const fn new() -> Self {
+        Self { chars: Vec::new(), cursor: 0, scroll: 0, pending_col: 0, indicator: "" }
+    }
+
+    pub fn set_indicator(&mut self, indicator: &'static str) {
+        self.indicator = indicator;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chars.is_empty()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.chars.is_full()
+    }
+
+    pub fn chars(&self) -> impl Iterator<Item = char> + '_ {
+        self.chars.iter().copied()
+    }
+
+    // Insert a character at the cursor and move past it
+    pub fn insert(&mut self, c: char) -> Result<(), Full> {
+        self.chars.insert(self.cursor, c).map_err(|_| Full)?;
+        self.cursor += 1;
+        Ok(())
+    }
+
+    // Delete the character before the cursor
+    pub fn backspace(&mut self) {
+        if self.cursor > 0 {
+            self.cursor -= 1;
+            self.chars.remove(self.cursor);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.chars.clear();
+        self.cursor = 0;
+        self.scroll = 0;
+    }
+
+    pub fn left(&mut self) {
+        self.cursor = self.cursor.saturating_sub(1);
+    }
+
+    pub fn right(&mut self) {
+        self.cursor = (self.cursor + 1).min(self.chars.len());
+    }
+
+    // Keep `position` inside a visible window of `width` characters
+    fn follow(&mut self, position: usize, width: usize) {
+        if position < self.scroll {
+            self.scroll = position;
+        } else if position >= self.scroll + width {
+            self.scroll = position + 1 - width;
+        }
+    }
+
+    // Top line: the visible part of the buffer, with `pending` inserted at the cursor.
+    // Returns the column of the last pending character (or of the cursor).
+    fn draw_text<D: Display>(&mut self, lcd: &mut D, pending: &str) -> u8 {
+        let position = self.cursor + pending.chars().count().saturating_sub(1);
+        let width = lcd.columns() as usize;
+        self.follow(position, width);
+
+        lcd.clear();
+        lcd.set_cursor_pos((0, 0));
+
+        let (before, after) = self.chars.split_at(self.cursor);
+        let text = before
+            .iter()
+            .copied()
+            .chain(pending.chars())
+            .chain(after.iter().copied());
+        for c in text.skip(self.scroll).take(width) {
+            lcd.write_char(c);
+        }
+
+        (position - self.scroll) as u8
+    }
+
+    // Bottom line: the input mode, then `status`
+    fn draw_status<D: Display>(&self, lcd: &mut D, status: &str) {
+        lcd.set_cursor_pos((0, 1));
+        lcd.write_str(self.indicator);
+        lcd.set_cursor_pos((INDICATOR_WIDTH, 1));
+        lcd.write_str(status);
+    }
+
+    // Draw the buffer with the cursor on it, `status` goes on the bottom line
+    pub fn show<D: Display>(&mut self, lcd: &mut D, status: &str) {
+        self.show_with(lcd, |lcd| lcd.write_str(status));
+    }
+
+    // The same with the code of `c` as the status, in Morse glyphs
+    pub fn show_code<D: Display>(&mut self, lcd: &mut D, c: char) {
+        self.show_with(lcd, |lcd| write_code(lcd, c));
+    }
+
+    fn show_with<D: Display>(&mut self, lcd: &mut D, status: impl FnOnce(&mut D)) {
+        let col = self.draw_text(lcd, "");
+
+        let mut fill = String::<8>::new();
+        write!(fill, "{}/{}", self.chars.len(), CAPACITY).ok();
+        self.draw_status(lcd, "");
+        status(lcd);
+        lcd.set_cursor_pos((lcd.columns() - fill.len() as u8, 1));
+        lcd.write_str(&fill);
+
+        lcd.set_cursor_pos((col, 0));
+        lcd.set_cursor(Cursor::Underline);
+    }
+
+    // Draw the text being typed (a multitap character or a predicted word) in
+    // place, under a blinking cursor, with `hint` on the bottom line
+    pub fn show_pending<D: Display>(&mut self, lcd: &mut D, pending: &str, hint: &str) {
+        self.pending_col = self.draw_text(lcd, pending);
+        self.draw_status(lcd, hint);
+
+        lcd.set_cursor_pos((self.pending_col, 0));
+        lcd.set_cursor(Cursor::Blink);
+    }
+
+    // Shrink the countdown bar to `cells`, leaving the cursor on the pending character
+    pub fn show_countdown<D: Display>(&self, lcd: &mut D, cells: usize) {
+        lcd.set_cursor_pos((lcd.columns() - COUNTDOWN_CELLS as u8, 1));
+        for i in 0..COUNTDOWN_CELLS {
+            lcd.write_glyph(Glyph::Bar(if i < cells { 5 } else { 0 }));
+        }
+
+        lcd.set_cursor_pos((self.pending_col, 0));
+    }
+}
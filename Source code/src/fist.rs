@@ -0,0 +1,464 @@
+//! Sending-quality ("fist") analyzer for manual keying.
+//!
+//! While the user keys on the straight key (or holds the '0' key of the keypad
+//! as one), every mark and space is timed. Once the user stops, the timings are
+//! graded against the standard Morse proportions: a dash is 3 dots long, the
+//! break inside a letter is 1 dot, between letters 3 dots and between words 7.
+
+use core::fmt::Write;
+
+use embassy_rp::gpio::Input;
+use embassy_time::{Duration, Instant, Timer};
+use heapless::{String, Vec};
+
+use crate::display::Display;
+use crate::keyer::Keyer;
+use crate::keypad::Keypad;
+use crate::morse::decode;
+use crate::ui::{show_lines, wait_key};
+
+pub const MAX_ELEMENTS: usize = 128;
+
+// Edges closer than this to the previous one are contact bounce
+const DEBOUNCE_MS: u32 = 5;
+
+// A silence this long ends the session and shows the report
+const SESSION_END_MS: u32 = 3000;
+
+// The keypad key that acts as a straight key, here and in the timeline
+pub const KEYPAD_ELEMENT_KEY: char = '0';
+
+#[derive(Copy, Clone)]
+struct Element {
+    mark: u32,
+    // The space after the mark, 0 for the last one
+    space: u32,
+}
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum Gap {
+    Element,
+    Letter,
+    Word,
+}
+
+pub struct Report {
+    // Estimated length of one dot, in milliseconds
+    pub unit_ms: u32,
+    // Speed of the characters themselves (PARIS standard)
+    pub char_wpm: u32,
+    // Speed including the actual gaps the user left
+    pub effective_wpm: u32,
+    // Average dash length over average dot length, in tenths (ideal: 30)
+    pub dash_dot_ratio: u32,
+    // Average deviation of the breaks inside letters, in % of their mean
+    pub element_gap_spread: u32,
+    // Average break between letters, in % of the ideal 3 dots
+    pub letter_gap_accuracy: Option<u32>,
+    // Average break between words, in % of the ideal 7 dots
+    pub word_gap_accuracy: Option<u32>,
+    // What the keying decodes to
+    pub text: String<64>,
+    // Positions in `text` of the characters sent ambiguously
+    pub ambiguous: Vec<usize, 16>,
+}
+
+// The gap a space of `space` ms stands for with dots of `unit` ms, and whether
+// it is too close to a boundary to tell which gap was meant
+pub fn classify(space: u32, unit: u32) -> (Gap, bool) {
+    // Boundaries halfway between the ideal 1, 3 and 7 units
+    let gap = if space < 2 * unit {
+        Gap::Element
+    } else if space < 5 * unit {
+        Gap::Letter
+    } else {
+        Gap::Word
+    };
+
+    let ambiguous =
+        space.abs_diff(2 * unit) * 5 < 2 * unit || space.abs_diff(5 * unit) * 5 < 4 * unit;
+
+    (gap, ambiguous)
+}
+
+pub struct Analyzer {
+    elements: Vec<Element, MAX_ELEMENTS>,
+}
+
+impl Analyzer {
+    pub const fn new() -> Self {
+        Self { elements: Vec::new() }
+    }
+
+    pub fn clear(&mut self) {
+        self.elements.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.elements.is_empty()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.elements.is_full()
+    }
+
+    pub fn push_mark(&mut self, ms: u32) {
+        self.elements.push(Element { mark: ms, space: 0 }).ok();
+    }
+
+    pub fn push_space(&mut self, ms: u32) {
+        if let Some(last) = self.elements.last_mut() {
+            last.space = ms;
+        }
+    }
+
+    // Split the marks into dots and dashes, returning the threshold between them
+    fn dash_threshold(&self) -> u32 {
+        let min = self.elements.iter().map(|e| e.mark).min().unwrap_or(0);
+        let max = self.elements.iter().map(|e| e.mark).max().unwrap_or(0);
+
+        // All marks have the same length: compare them with the shortest space
+        if max < 2 * min {
+            let shortest_space = self
+                .elements
+                .iter()
+                .map(|e| e.space)
+                .filter(|s| *s > 0)
+                .min()
+                .unwrap_or(max);
+
+            return if min >= 2 * shortest_space { min } else { max + 1 };
+        }
+
+        // Two-cluster k-means over the mark lengths
+        let mut threshold = (min + max) / 2;
+        for _ in 0..4 {
+            let (dots, dashes) = self.cluster_means(threshold);
+            match (dots, dashes) {
+                (Some(dot), Some(dash)) => threshold = (dot + dash) / 2,
+                _ => break,
+            }
+        }
+
+        threshold
+    }
+
+    fn cluster_means(&self, threshold: u32) -> (Option<u32>, Option<u32>) {
+        let (mut dot_sum, mut dot_count, mut dash_sum, mut dash_count) = (0, 0, 0, 0);
+
+        for e in &self.elements {
+            if e.mark < threshold {
+                dot_sum += e.mark;
+                dot_count += 1;
+            } else {
+                dash_sum += e.mark;
+                dash_count += 1;
+            }
+        }
+
+        (
+            (dot_count > 0).then(|| dot_sum / dot_count),
+            (dash_count > 0).then(|| dash_sum / dash_count),
+        )
+    }
+
+    pub fn analyze(&self) -> Option<Report> {
+        if self.elements.is_empty() {
+            return None;
+        }
+
+        let threshold = self.dash_threshold();
+        let (dot_mean, dash_mean) = self.cluster_means(threshold);
+
+        let unit = match (dot_mean, dash_mean) {
+            (Some(dot), Some(dash)) => (dot + dash / 3) / 2,
+            (Some(dot), None) => dot,
+            (None, Some(dash)) => dash / 3,
+            (None, None) => return None,
+        }
+        .max(1);
+
+        let mut text = String::<64>::new();
+        let mut ambiguous = Vec::<usize, 16>::new();
+        let mut code = String::<8>::new();
+        let mut letter_ambiguous = false;
+        let mut ideal_units = 0;
+        let mut elapsed = 0;
+
+        let (mut element_gaps, mut element_gap_count) = (0, 0);
+        let (mut letter_gaps, mut letter_gap_count) = (0, 0);
+        let (mut word_gaps, mut word_gap_count) = (0, 0);
+
+        let last = self.elements.len() - 1;
+        for (i, e) in self.elements.iter().enumerate() {
+            elapsed += e.mark;
+            if e.mark < threshold {
+                code.push('.').ok();
+                ideal_units += 1;
+            } else {
+                code.push('-').ok();
+                ideal_units += 3;
+            }
+
+            let gap = if i == last {
+                Gap::Letter
+            } else {
+                elapsed += e.space;
+                let (gap, unclear) = classify(e.space, unit);
+                letter_ambiguous |= unclear;
+
+                match gap {
+                    Gap::Element => {
+                        element_gaps += e.space;
+                        element_gap_count += 1;
+                        ideal_units += 1;
+                    }
+                    Gap::Letter => {
+                        letter_gaps += e.space;
+                        letter_gap_count += 1;
+                        ideal_units += 3;
+                    }
+                    Gap::Word => {
+                        word_gaps += e.space;
+                        word_gap_count += 1;
+                        ideal_units += 7;
+                    }
+                }
+
+                gap
+            };
+
+            if gap == Gap::Element {
+                continue;
+            }
+
+            // The letter is complete
+            let ch = decode(&code);
+            if letter_ambiguous || ch.is_none() {
+                ambiguous.push(text.len()).ok();
+            }
+            text.push(ch.unwrap_or('?')).ok();
+            if gap == Gap::Word {
+                text.push(' ').ok();
+            }
+
+            // A doubtful break also casts doubt on the letter that follows it
+            letter_ambiguous = i != last && classify(e.space, unit).1;
+            code.clear();
+        }
+
+        let element_mean = (element_gap_count > 0).then(|| element_gaps / element_gap_count);
+        let element_gap_spread = match element_mean {
+            Some(mean) if mean > 0 => {
+                let deviation: u32 = self
+                    .elements
+                    .iter()
+                    .take(last)
+                    .filter(|e| classify(e.space, unit).0 == Gap::Element)
+                    .map(|e| e.space.abs_diff(mean))
+                    .sum();
+
+                deviation * 100 / element_gap_count / mean
+            }
+            _ => 0,
+        };
+
+        Some(Report {
+            unit_ms: unit,
+            char_wpm: 1200 / unit,
+            effective_wpm: ideal_units * 1200 / elapsed.max(1),
+            dash_dot_ratio: match (dot_mean, dash_mean) {
+                (Some(dot), Some(dash)) => dash * 10 / dot.max(1),
+                _ => 0,
+            },
+            element_gap_spread,
+            letter_gap_accuracy: (letter_gap_count > 0)
+                .then(|| letter_gaps / letter_gap_count * 100 / (3 * unit)),
+            word_gap_accuracy: (word_gap_count > 0)
+                .then(|| word_gaps / word_gap_count * 100 / (7 * unit)),
+            text,
+            ambiguous,
+        })
+    }
+}
+
+// Show the report one page at a time, returns false if the user asked to exit
+async fn show_report<D: Display>(lcd: &mut D, keypad: &mut Keypad, report: &Report) -> bool {
+    defmt::info!(
+        "Fist: '{}' unit {} ms, {} wpm ({} effective), dash/dot {}/10, element gap spread {}%, letter gap {}%, word gap {}%",
+        report.text.as_str(),
+        report.unit_ms,
+        report.char_wpm,
+        report.effective_wpm,
+        report.dash_dot_ratio,
+        report.element_gap_spread,
+        report.letter_gap_accuracy,
+        report.word_gap_accuracy,
+    );
+
+    let mut top = String::<16>::new();
+    let mut bottom = String::<16>::new();
+
+    for page in 0..3 {
+        top.clear();
+        bottom.clear();
+
+        match page {
+            0 => {
+                // The decoded text, with a caret under every doubtful character
+                for ch in report.text.chars().take(16) {
+                    top.push(ch).ok();
+                }
+                for i in 0..top.len() {
+                    bottom.push(if report.ambiguous.contains(&i) { '^' } else { ' ' }).ok();
+                }
+                if report.ambiguous.is_empty() {
+                    bottom.clear();
+                    bottom.push_str("Clean sending!").ok();
+                }
+            }
+            1 => {
+                write!(top, "WPM {} eff {}", report.char_wpm, report.effective_wpm).ok();
+                write!(
+                    bottom,
+                    "Dash/dot {}.{}",
+                    report.dash_dot_ratio / 10,
+                    report.dash_dot_ratio % 10
+                )
+                .ok();
+            }
+            _ => {
+                write!(top, "Elem gap +-{}%", report.element_gap_spread).ok();
+                match report.letter_gap_accuracy {
+                    Some(pct) => write!(bottom, "Ltr {}% ", pct).ok(),
+                    None => write!(bottom, "Ltr -- ").ok(),
+                };
+                match report.word_gap_accuracy {
+                    Some(pct) => write!(bottom, "Wd {}%", pct).ok(),
+                    None => write!(bottom, "Wd --").ok(),
+                };
+            }
+        }
+
+        show_lines(lcd, &top, &bottom);
+
+        // Any key moves to the next page, '*' leaves the analyzer
+        if wait_key(keypad).await == '*' {
+            return false;
+        }
+    }
+
+    true
+}
+
+// Run the analyzer until the user leaves it with '*'
+pub async fn run<D: Display>(
+    lcd: &mut D,
+    keypad: &mut Keypad,
+    keyer: &mut Keyer,
+    straight_key: &Input<'static>,
+) {
+    let mut analyzer = Analyzer::new();
+
+    loop {
+        show_lines(lcd, "Fist analyzer", "Key now, * exit");
+        analyzer.clear();
+
+        let mut down = false;
+        let mut last_edge = Instant::now();
+
+        loop {
+            let is_down = straight_key.is_low() || keypad.is_held(KEYPAD_ELEMENT_KEY);
+            let elapsed = Instant::now().duration_since(last_edge).as_millis() as u32;
+
+            if is_down != down && elapsed >= DEBOUNCE_MS {
+                if is_down {
+                    if !analyzer.is_empty() {
+                        analyzer.push_space(elapsed);
+                    }
+                } else {
+                    analyzer.push_mark(elapsed);
+                }
+
+                keyer.sidetone(is_down).await;
+                down = is_down;
+                last_edge = Instant::now();
+            } else if !down && !analyzer.is_empty() && (elapsed >= SESSION_END_MS || analyzer.is_full()) {
+                break;
+            } else if !down && analyzer.is_empty() && keypad.is_held('*') {
+                // Wait for the release so the key does not leak into the main loop
+                while keypad.is_held('*') {
+                    Timer::after(Duration::from_millis(10)).await;
+                }
+                keypad.clear();
+                return;
+            }
+
+            Timer::after(Duration::from_millis(1)).await;
+        }
+
+        keyer.sidetone(false).await;
+
+        // The keypad key used for keying also went to the event queue
+        keypad.clear();
+
+        if let Some(report) = analyzer.analyze() {
+            if !show_report(lcd, keypad, &report).await {
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Key `letters`, given as their codes, with ideal timing at `unit` ms
+    fn keyed(letters: &[&str], unit: u32) -> Analyzer {
+        let mut analyzer = Analyzer::new();
+        for (i, code) in letters.iter().enumerate() {
+            for (j, symbol) in code.chars().enumerate() {
+                analyzer.push_mark(if symbol == '-' { 3 * unit } else { unit });
+                if j + 1 < code.len() {
+                    analyzer.push_space(unit);
+                } else if i + 1 < letters.len() {
+                    analyzer.push_space(3 * unit);
+                }
+            }
+        }
+        analyzer
+    }
+
+    #[test]
+    fn classify_splits_halfway_between_the_ideal_gaps() {
+        assert!(classify(100, 100) == (Gap::Element, false));
+        assert!(classify(300, 100) == (Gap::Letter, false));
+        assert!(classify(700, 100) == (Gap::Word, false));
+    }
+
+    #[test]
+    fn classify_flags_gaps_near_a_boundary() {
+        assert!(classify(210, 100) == (Gap::Letter, true));
+        assert!(classify(190, 100) == (Gap::Element, true));
+        assert!(classify(480, 100) == (Gap::Letter, true));
+    }
+
+    #[test]
+    fn analyze_reads_clean_keying() {
+        let report = keyed(&["...", "---", "..."], 100).analyze().unwrap();
+
+        assert_eq!(report.text.as_str(), "SOS");
+        assert!(report.ambiguous.is_empty());
+        assert_eq!(report.unit_ms, 100);
+        assert_eq!(report.char_wpm, 12);
+        assert_eq!(report.dash_dot_ratio, 30);
+        assert_eq!(report.letter_gap_accuracy, Some(100));
+        assert_eq!(report.word_gap_accuracy, None);
+    }
+
+    #[test]
+    fn analyze_needs_keying() {
+        assert!(Analyzer::new().analyze().is_none());
+    }
+}
@@ -0,0 +1,282 @@
+//! Morse output through the three LEDs and the buzzer.
+//!
+//! The pins belong to `keyer_task`, which plays whatever is queued on its
+//! channel. The rest of the firmware talks to it through the `Keyer` handle:
+//! it can queue characters and carry on (so the user types ahead while a
+//! character is still sounding), or wait until everything queued was played.
+//!
+//! A transmission can be aborted at any time, which silences the outputs at
+//! once and drops whatever is still queued, or paused and resumed; a pause
+//! takes effect at the next element boundary.
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use embassy_futures::select::{select, Either};
+use embassy_rp::gpio::{Level, Output};
+use embassy_rp::peripherals::{PIN_16, PIN_18, PIN_19, PIN_20};
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::channel::Channel;
+use embassy_sync::signal::Signal;
+use embassy_time::{Duration, Timer};
+
+use crate::morse::morse_table;
+
+// Length of a dot at the default speed; every other timing is a multiple of it
+pub const DEFAULT_UNIT_MS: u64 = 200;
+
+enum Command {
+    Char(char),
+    Unit(u64),
+    Sound(bool),
+    Sidetone(bool),
+    // Report once everything queued before it was played
+    Flush,
+}
+
+static COMMANDS: Channel<CriticalSectionRawMutex, Command, 16> = Channel::new();
+static FLUSHED: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+
+// Transmission control, outside of the queue so it acts right away
+static ABORT: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+static RESUMED: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+static PAUSED: AtomicBool = AtomicBool::new(false);
+
+// Whether the outputs are on right now, for the keying timeline
+static KEYED: AtomicBool = AtomicBool::new(false);
+
+// Index in its code of the last element that started, for following along
+static ELEMENT: Signal<CriticalSectionRawMutex, usize> = Signal::new();
+
+struct Aborted;
+
+// The LEDs and the buzzer, owned by the keying task
+pub struct Transmitter {
+    led1: Output<'static>,
+    led2: Output<'static>,
+    led3: Output<'static>,
+    buzzer: Output<'static>,
+    unit: Duration,
+    // Whether the buzzer sounds along with the LEDs
+    sound: bool,
+}
+
+impl Transmitter {
+    // Initialize the LEDs and buzzer
+    pub fn new(pin18: PIN_18, pin19: PIN_19, pin20: PIN_20, pin16: PIN_16) -> Self {
+        Self {
+            led1: Output::new(pin18, Level::Low),
+            led2: Output::new(pin19, Level::Low),
+            led3: Output::new(pin20, Level::Low),
+            buzzer: Output::new(pin16, Level::Low),
+            unit: Duration::from_millis(DEFAULT_UNIT_MS),
+            sound: true,
+        }
+    }
+
+    // Wait for `units` dot lengths, unless the transmission is aborted
+    async fn hold(&self, units: u32) -> Result<(), Aborted> {
+        match select(Timer::after(self.unit * units), ABORT.wait()).await {
+            Either::First(_) => Ok(()),
+            Either::Second(_) => Err(Aborted),
+        }
+    }
+
+    // Stay here while the transmission is paused
+    async fn element_boundary(&self) -> Result<(), Aborted> {
+        while PAUSED.load(Ordering::Relaxed) {
+            if let Either::Second(_) = select(RESUMED.wait(), ABORT.wait()).await {
+                return Err(Aborted);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn buzz(&mut self, on: bool) {
+        self.buzzer.set_level(if on && self.sound { Level::High } else { Level::Low });
+    }
+
+    async fn dot(&mut self) -> Result<(), Aborted> {
+        self.led2.set_high();
+        self.buzz(true);
+        KEYED.store(true, Ordering::Relaxed);
+        let result = self.hold(1).await;
+        self.led2.set_low();
+        self.buzz(false);
+        KEYED.store(false, Ordering::Relaxed);
+        result
+    }
+
+    async fn dash(&mut self) -> Result<(), Aborted> {
+        self.led1.set_high();
+        self.led2.set_high();
+        self.led3.set_high();
+        self.buzz(true);
+        KEYED.store(true, Ordering::Relaxed);
+        let result = self.hold(3).await;
+        self.led1.set_low();
+        self.led2.set_low();
+        self.led3.set_low();
+        self.buzz(false);
+        KEYED.store(false, Ordering::Relaxed);
+        result
+    }
+
+    // Play a string of dots and dashes
+    async fn play_code(&mut self, code: &str) -> Result<(), Aborted> {
+        for (index, symbol) in code.chars().enumerate() {
+            self.element_boundary().await?;
+            ELEMENT.signal(index);
+
+            match symbol {
+                '.' => self.dot().await?,
+                '-' => self.dash().await?,
+                _ => {}
+            }
+
+            // Break between signals
+            self.hold(1).await?;
+        }
+
+        Ok(())
+    }
+
+    // Play a single character, followed by the break between letters
+    async fn play_char(&mut self, c: char) -> Result<(), Aborted> {
+        if let Some(code) = morse_table(c) {
+            self.play_code(code).await?;
+
+            // Break between letters
+            self.hold(3).await?;
+        } else if c == ' ' {
+            // What the break between words adds to the one between letters
+            self.hold(4).await?;
+        }
+
+        Ok(())
+    }
+
+    // Follow a manual key: the middle LED and the buzzer stay on while it is down
+    fn sidetone(&mut self, on: bool) {
+        self.led2.set_level(if on { Level::High } else { Level::Low });
+        self.buzz(on);
+        KEYED.store(on, Ordering::Relaxed);
+    }
+}
+
+// Carry out a command, the queued Morse is skipped after an abort
+fn settle(transmitter: &mut Transmitter, command: Command) {
+    match command {
+        Command::Unit(unit_ms) => transmitter.unit = Duration::from_millis(unit_ms),
+        Command::Sound(on) => transmitter.sound = on,
+        Command::Sidetone(on) => transmitter.sidetone(on),
+        Command::Flush => FLUSHED.signal(()),
+        Command::Char(_) => {}
+    }
+}
+
+#[embassy_executor::task]
+pub async fn keyer_task(mut transmitter: Transmitter) {
+    loop {
+        let command = match select(COMMANDS.receive(), ABORT.wait()).await {
+            Either::First(command) => command,
+            // Nothing was playing, only a pause to forget
+            Either::Second(_) => {
+                PAUSED.store(false, Ordering::Relaxed);
+                continue;
+            }
+        };
+
+        let result = match command {
+            Command::Char(c) => transmitter.play_char(c).await,
+            command => {
+                settle(&mut transmitter, command);
+                Ok(())
+            }
+        };
+
+        if result.is_err() {
+            defmt::info!("Transmission aborted");
+            PAUSED.store(false, Ordering::Relaxed);
+            while let Ok(command) = COMMANDS.try_receive() {
+                settle(&mut transmitter, command);
+            }
+        }
+    }
+}
+
+// Where the rest of the firmware sends Morse from
+pub struct Keyer;
+
+impl Keyer {
+    pub fn new() -> Self {
+        Self
+    }
+
+    // Change the sending speed through the length of a dot
+    pub async fn set_unit(&mut self, unit_ms: u64) {
+        COMMANDS.send(Command::Unit(unit_ms)).await;
+    }
+
+    // Let the buzzer sound with the LEDs, or keep it quiet
+    pub async fn set_sound(&mut self, on: bool) {
+        COMMANDS.send(Command::Sound(on)).await;
+    }
+
+    // Queue a character and return while it is (or before it is) played
+    pub async fn queue_char(&mut self, c: char) {
+        COMMANDS.send(Command::Char(c)).await;
+    }
+
+    // Wait until everything queued so far was played
+    pub async fn flush(&self) {
+        FLUSHED.reset();
+        COMMANDS.send(Command::Flush).await;
+        FLUSHED.wait().await;
+    }
+
+    // Play a single character, followed by the break between letters
+    pub async fn play_char(&mut self, c: char) {
+        self.queue_char(c).await;
+        self.flush().await;
+    }
+
+    // Stop right away and forget everything still queued
+    pub fn abort(&self) {
+        ABORT.signal(());
+    }
+
+    // Hold the transmission at the next element boundary
+    pub fn pause(&self) {
+        PAUSED.store(true, Ordering::Relaxed);
+    }
+
+    pub fn resume(&self) {
+        PAUSED.store(false, Ordering::Relaxed);
+        RESUMED.signal(());
+    }
+
+    // Wait for the next element to start, returns its index in the code
+    pub async fn element(&self) -> usize {
+        ELEMENT.wait().await
+    }
+
+    // Forget the last element signalled, nobody waited for it
+    pub fn clear_element(&self) {
+        ELEMENT.reset();
+    }
+
+    pub fn is_paused(&self) -> bool {
+        PAUSED.load(Ordering::Relaxed)
+    }
+
+    // Whether a mark is being sent (or keyed by hand) right now
+    pub fn is_keyed(&self) -> bool {
+        KEYED.load(Ordering::Relaxed)
+    }
+
+    // Follow a manual key: the middle LED and the buzzer stay on while it is down
+    pub async fn sidetone(&mut self, on: bool) {
+        COMMANDS.send(Command::Sidetone(on)).await;
+    }
+}
@@ -0,0 +1,486 @@
+//! Which physical key does what.
+//!
+//! The rest of the firmware works with logical keys, named after the labels of
+//! the original keypad ('1'..'9', '0', '*', '#' and the command keys '!', '^',
+//! ')' and '('). The layout says which logical key sits at each row and column
+//! of the matrix, so a keypad with a different sticker (or a 3x4 phone pad)
+//! only needs a different layout. One can be picked from the overlays below or
+//! taught key by key, and it is saved in flash.
+//!
+//! Which keys switch the mode, open the function layer and run the commands is
+//! data too: the actions can be moved between the keys outside the letters,
+//! and the function layer digits between the commands. These bindings are
+//! saved in flash as well.
+
+use core::cell::Cell;
+use core::fmt::Write;
+
+use embassy_futures::select::{select, Either};
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_time::{Duration, Instant, Timer};
+use heapless::String;
+
+use crate::display::Display;
+use crate::keypad::Keypad;
+use crate::storage::{self, Storage};
+use crate::ui::{show_lines, show_menu, wait_key};
+
+// The logical key at each row and column of the matrix
+pub type Layout = [[char; 4]; 4];
+
+// A position of the matrix without a key
+pub const NO_KEY: char = ' ';
+
+pub const OVERLAYS: &[(&str, Layout)] = &[
+    (
+        "Original 4x4",
+        [
+            ['1', '2', '3', '!'],
+            ['4', '5', '6', '^'],
+            ['7', '8', '9', ')'],
+            ['*', '0', '#', '('],
+        ],
+    ),
+    // A: send the message, B: HELLO, C: SOS, D: demo quiz
+    (
+        "Letters A-D",
+        [
+            ['1', '2', '3', '('],
+            ['4', '5', '6', '!'],
+            ['7', '8', '9', '^'],
+            ['*', '0', '#', ')'],
+        ],
+    ),
+    // No fourth column, the commands are in the function layer
+    (
+        "Phone 3x4",
+        [
+            ['1', '2', '3', NO_KEY],
+            ['4', '5', '6', NO_KEY],
+            ['7', '8', '9', NO_KEY],
+            ['*', '0', '#', NO_KEY],
+        ],
+    ),
+];
+
+// What the keys outside the letters do in the editor
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum Binding {
+    ModeSwitch,
+    Function,
+    // One of the commands, named after its key on the original keypad
+    Command(char),
+}
+
+// Every action a key can be bound to, with its name in the bindings menu
+pub const ACTIONS: [(Binding, &str); 7] = [
+    (Binding::ModeSwitch, "Mode"),
+    (Binding::Function, "Function"),
+    (Binding::Command('*'), "Fun facts"),
+    (Binding::Command('!'), "HELLO"),
+    (Binding::Command('^'), "S.O.S."),
+    (Binding::Command(')'), "Demo quiz"),
+    (Binding::Command('('), "Send message"),
+];
+
+// The fun facts key, which also aborts a transmission and cancels typing
+pub const BACK: Binding = Binding::Command('*');
+
+// The keys the actions can be moved between, the letter keys stay letters
+const BINDABLE_KEYS: [char; ACTIONS.len()] = ['#', '1', '*', '!', '^', ')', '('];
+
+// The digits of the function layer that can reach a command, the others
+// already do something there
+const FUNCTION_DIGITS: [char; 4] = ['2', '3', '8', '9'];
+
+// The key of each of `ACTIONS`, and for the commands the digit that reaches
+// them from the function layer too (for keypads without their keys)
+#[derive(Copy, Clone, PartialEq)]
+pub struct Bindings {
+    pub keys: [char; ACTIONS.len()],
+    pub function_keys: [char; ACTIONS.len()],
+}
+
+impl Bindings {
+    pub const DEFAULT: Self = Self {
+        keys: BINDABLE_KEYS,
+        function_keys: [NO_KEY, NO_KEY, NO_KEY, '2', '3', '8', '9'],
+    };
+
+    // Bindings read back from flash must give every action its own key, and
+    // the function layer digits at most once, to commands only
+    pub fn is_valid(&self) -> bool {
+        let function_keys = self.function_keys.iter().filter(|key| **key != NO_KEY);
+
+        BINDABLE_KEYS.iter().all(|key| self.keys.contains(key))
+            && function_keys.clone().all(|key| FUNCTION_DIGITS.contains(key))
+            && function_keys.clone().enumerate().all(|(i, key)| !function_keys.clone().skip(i + 1).any(|other| other == key))
+            && ACTIONS
+                .iter()
+                .zip(self.function_keys)
+                .all(|((binding, _), key)| matches!(binding, Binding::Command(_)) || key == NO_KEY)
+    }
+
+    // Whether `layout` has the mode and function keys, without them the
+    // menus (and this one, to undo it) cannot be reached
+    pub fn fit(&self, layout: &Layout) -> bool {
+        ACTIONS
+            .iter()
+            .zip(self.keys)
+            .filter(|((binding, _), _)| matches!(binding, Binding::ModeSwitch | Binding::Function))
+            .all(|(_, key)| layout.iter().flatten().any(|k| *k == key))
+    }
+}
+
+// Every logical key in the order they are taught; the ones after the
+// first `ESSENTIAL_KEYS` can be left out
+const KEYS: &[(char, &str)] = &[
+    ('1', "1 / function"),
+    ('2', "2 ABC / up"),
+    ('3', "3 DEF"),
+    ('4', "4 GHI / left"),
+    ('5', "5 JKL / select"),
+    ('6', "6 MNO / right"),
+    ('7', "7 PQRS"),
+    ('8', "8 TUV / down"),
+    ('9', "9 WXYZ"),
+    ('0', "0"),
+    ('*', "* facts / back"),
+    ('#', "# mode"),
+    ('!', "HELLO"),
+    ('^', "S.O.S."),
+    (')', "Demo quiz"),
+    ('(', "Send message"),
+];
+const ESSENTIAL_KEYS: usize = 12;
+
+// Teaching gives up after this long without a key
+const TEACH_TIMEOUT: Duration = Duration::from_secs(10);
+
+static LAYOUT: Mutex<CriticalSectionRawMutex, Cell<Layout>> = Mutex::new(Cell::new(OVERLAYS[0].1));
+static BINDINGS: Mutex<CriticalSectionRawMutex, Cell<Bindings>> = Mutex::new(Cell::new(Bindings::DEFAULT));
+
+pub fn layout() -> Layout {
+    LAYOUT.lock(|layout| layout.get())
+}
+
+pub fn set(layout: Layout) {
+    LAYOUT.lock(|active| active.set(layout));
+}
+
+pub fn bindings() -> Bindings {
+    BINDINGS.lock(|bindings| bindings.get())
+}
+
+pub fn set_bindings(bindings: Bindings) {
+    BINDINGS.lock(|active| active.set(bindings));
+}
+
+// The key `action` is bound to
+pub fn key_of(action: Binding) -> char {
+    let keys = bindings().keys;
+    ACTIONS.iter().position(|(binding, _)| *binding == action).map_or(NO_KEY, |i| keys[i])
+}
+
+pub fn binding(key: char) -> Option<Binding> {
+    bindings().keys.iter().position(|k| *k == key).map(|i| ACTIONS[i].0)
+}
+
+pub fn function_command(key: char) -> Option<char> {
+    let i = bindings().function_keys.iter().position(|k| *k == key && key != NO_KEY)?;
+    match ACTIONS[i].0 {
+        Binding::Command(command) => Some(command),
+        _ => None,
+    }
+}
+
+// A layout read back from flash must only hold known keys, each at most once
+pub fn is_valid(layout: &Layout) -> bool {
+    let keys = layout.iter().flatten().filter(|key| **key != NO_KEY);
+
+    keys.clone().all(|key| KEYS.iter().any(|(k, _)| k == key))
+        && keys.clone().enumerate().all(|(i, key)| !keys.clone().skip(i + 1).any(|other| other == key))
+        && KEYS[..ESSENTIAL_KEYS].iter().all(|(k, _)| layout.iter().flatten().any(|key| key == k))
+}
+
+// Wait for a single key to be pressed and released, None after the timeout
+async fn wait_position(keypad: &mut Keypad) -> Option<usize> {
+    let start = Instant::now();
+    let mut pressed = None;
+
+    loop {
+        let down = keypad.down_positions();
+
+        match pressed {
+            None if down.count_ones() == 1 => pressed = Some(down.trailing_zeros() as usize),
+            Some(position) if down == 0 => return Some(position),
+            None if Instant::now().duration_since(start) >= TEACH_TIMEOUT => return None,
+            _ => {}
+        }
+
+        Timer::after(Duration::from_millis(10)).await;
+    }
+}
+
+// Ask for every key in turn, None if the user stopped answering
+async fn teach<D: Display>(lcd: &mut D, keypad: &mut Keypad) -> Option<Layout> {
+    let mut layout = [[NO_KEY; 4]; 4];
+
+    for (i, (key, name)) in KEYS.iter().enumerate() {
+        let bottom = if i < ESSENTIAL_KEYS { "" } else { "Used key = skip" };
+        show_lines(lcd, name, bottom);
+
+        loop {
+            let position = wait_position(keypad).await?;
+            let slot = &mut layout[position / 4][position % 4];
+
+            if *slot == NO_KEY {
+                *slot = *key;
+                break;
+            } else if i >= ESSENTIAL_KEYS {
+                break;
+            }
+
+            show_lines(lcd, name, "Already used");
+        }
+    }
+
+    Some(layout)
+}
+
+// Ask for the new key of action `name` among `allowed`; '5' keeps the current
+// one, and so does waiting
+async fn choose_key<D: Display>(lcd: &mut D, keypad: &mut Keypad, name: &str, current: &str, allowed: &[char]) -> Option<char> {
+    let mut bottom = String::<16>::new();
+    write!(bottom, "{} 5=keep", current).ok();
+    show_lines(lcd, name, &bottom);
+
+    loop {
+        match select(wait_key(keypad), Timer::after(TEACH_TIMEOUT)).await {
+            Either::First(key) if allowed.contains(&key) => return Some(key),
+            Either::First('5') | Either::Second(_) => return None,
+            Either::First(_) => show_lines(lcd, name, "Not allowed"),
+        }
+    }
+}
+
+// Give action `i` the key `key`, the action that had it takes the old one
+fn rebind(keys: &mut [char; ACTIONS.len()], i: usize, key: char) {
+    if let Some(other) = keys.iter().position(|k| *k == key) {
+        keys[other] = keys[i];
+    }
+    keys[i] = key;
+}
+
+// Move the actions to other keys until the user leaves with '*', then save
+async fn edit_bindings<D: Display>(lcd: &mut D, keypad: &mut Keypad, flash: &mut Storage) {
+    let mut edited = bindings();
+    let mut selected = 0;
+
+    loop {
+        show_menu(lcd, ACTIONS.len(), |i| ACTIONS[i].1, selected);
+
+        match wait_key(keypad).await {
+            '2' => selected = (selected + ACTIONS.len() - 1) % ACTIONS.len(),
+            '8' => selected = (selected + 1) % ACTIONS.len(),
+            '5' => {
+                let (binding, name) = ACTIONS[selected];
+
+                let mut current = String::<16>::new();
+                write!(current, "Key {}", edited.keys[selected]).ok();
+                if let Some(key) = choose_key(lcd, keypad, name, &current, &BINDABLE_KEYS).await {
+                    let previous = edited;
+                    rebind(&mut edited.keys, selected, key);
+
+                    if !edited.fit(&layout()) {
+                        edited = previous;
+                        show_lines(lcd, "Mode & function", "need a key here");
+                        Timer::after(Duration::from_millis(1500)).await;
+                        continue;
+                    }
+                }
+
+                if let Binding::Command(_) = binding {
+                    let function_key = match edited.function_keys[selected] {
+                        NO_KEY => '-',
+                        key => key,
+                    };
+                    current.clear();
+                    write!(current, "1 then {}", function_key).ok();
+                    if let Some(key) = choose_key(lcd, keypad, name, &current, &FUNCTION_DIGITS).await {
+                        rebind(&mut edited.function_keys, selected, key);
+                    }
+                }
+            }
+            '*' => break,
+            _ => {}
+        }
+    }
+
+    if edited == bindings() {
+        return;
+    }
+
+    set_bindings(edited);
+    let mut progress = storage::load(flash);
+    progress.bindings = edited;
+    storage::save(flash, &progress);
+
+    defmt::info!("Key bindings changed");
+    show_lines(lcd, "Keys saved", "");
+    Timer::after(Duration::from_millis(1000)).await;
+}
+
+// Pick an overlay, teach the keys or move the actions between them, until the
+// user leaves with '*'
+pub async fn run<D: Display>(lcd: &mut D, keypad: &mut Keypad, flash: &mut Storage) {
+    // The overlays, then teaching and the bindings
+    let entries = OVERLAYS.len() + 2;
+    let name = |i: usize| match OVERLAYS.get(i) {
+        Some((name, _)) => *name,
+        None if i == OVERLAYS.len() => "Teach the keys",
+        None => "Command keys",
+    };
+    let mut selected = 0;
+
+    loop {
+        show_menu(lcd, entries, name, selected);
+
+        let layout = match wait_key(keypad).await {
+            '2' => {
+                selected = (selected + entries - 1) % entries;
+                continue;
+            }
+            '8' => {
+                selected = (selected + 1) % entries;
+                continue;
+            }
+            '5' if selected == entries - 1 => {
+                edit_bindings(lcd, keypad, flash).await;
+                continue;
+            }
+            '5' => match OVERLAYS.get(selected) {
+                Some((_, layout)) => *layout,
+                None => {
+                    let taught = teach(lcd, keypad).await;
+                    // The keys pressed while teaching also went through the old layout
+                    keypad.clear();
+                    match taught {
+                        Some(layout) => layout,
+                        None => {
+                            show_lines(lcd, "Layout unchanged", "");
+                            Timer::after(Duration::from_millis(1000)).await;
+                            continue;
+                        }
+                    }
+                }
+            },
+            '*' => return,
+            _ => continue,
+        };
+
+        if !bindings().fit(&layout) {
+            show_lines(lcd, "No mode/function", "key, move first");
+            Timer::after(Duration::from_millis(1500)).await;
+            continue;
+        }
+
+        set(layout);
+        let mut progress = storage::load(flash);
+        progress.layout = layout;
+        storage::save(flash, &progress);
+
+        defmt::info!("Keypad layout changed to '{}'", name(selected));
+        show_lines(lcd, "Layout saved", name(selected));
+        Timer::after(Duration::from_millis(1000)).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_bindings_are_valid_and_fit_every_overlay() {
+        assert!(Bindings::DEFAULT.is_valid());
+        for (_, layout) in OVERLAYS {
+            assert!(is_valid(layout));
+            assert!(Bindings::DEFAULT.fit(layout));
+        }
+    }
+
+    #[test]
+    fn every_action_needs_its_own_key() {
+        let mut bindings = Bindings::DEFAULT;
+        bindings.keys[1] = bindings.keys[0];
+        assert!(!bindings.is_valid());
+
+        let mut bindings = Bindings::DEFAULT;
+        bindings.keys[2] = '5';
+        assert!(!bindings.is_valid());
+
+        let mut bindings = Bindings::DEFAULT;
+        bindings.keys.swap(0, 6);
+        assert!(bindings.is_valid());
+    }
+
+    #[test]
+    fn function_digits_reach_commands_only_once() {
+        let mut bindings = Bindings::DEFAULT;
+        bindings.function_keys[2] = '3';
+        bindings.function_keys[4] = NO_KEY;
+        assert!(bindings.is_valid());
+
+        // A digit that already does something in the function layer
+        let mut bindings = Bindings::DEFAULT;
+        bindings.function_keys[3] = '5';
+        assert!(!bindings.is_valid());
+
+        let mut bindings = Bindings::DEFAULT;
+        bindings.function_keys[3] = '3';
+        assert!(!bindings.is_valid());
+
+        // The mode switch and the function layer are not commands
+        let mut bindings = Bindings::DEFAULT;
+        bindings.function_keys[3] = NO_KEY;
+        bindings.function_keys[0] = '2';
+        assert!(!bindings.is_valid());
+    }
+
+    #[test]
+    fn bindings_fit_layouts_with_their_mode_and_function_keys() {
+        let phone = OVERLAYS[2].1;
+
+        // Commands may sit on keys the layout lacks, the function layer reaches them
+        let mut bindings = Bindings::DEFAULT;
+        bindings.keys.swap(2, 3);
+        assert!(bindings.fit(&phone));
+
+        let mut bindings = Bindings::DEFAULT;
+        bindings.keys.swap(0, 3);
+        assert!(!bindings.fit(&phone));
+        assert!(bindings.fit(&OVERLAYS[0].1));
+
+        let mut bindings = Bindings::DEFAULT;
+        bindings.keys.swap(1, 4);
+        assert!(!bindings.fit(&phone));
+    }
+
+    #[test]
+    fn layouts_need_known_keys_once_and_the_essential_ones() {
+        let mut layout = OVERLAYS[0].1;
+        layout[0][3] = NO_KEY;
+        assert!(is_valid(&layout));
+
+        layout[0][3] = '2';
+        assert!(!is_valid(&layout));
+
+        layout[0][3] = 'A';
+        assert!(!is_valid(&layout));
+
+        let mut layout = OVERLAYS[0].1;
+        layout[3][2] = NO_KEY;
+        assert!(!is_valid(&layout));
+    }
+}
@@ -0,0 +1,292 @@
+//! Driver for the 4x4 matrix keypad.
+//!
+//! The matrix is scanned by its own task, `scan_task`, so keys pressed while
+//! the rest of the firmware is busy (playing a message, for instance) are not
+//! lost: their events wait in a channel until the `Keypad` handle reads them.
+//!
+//! Events carry the logical key at the position, as set by the layout in
+//! `keymap`; positions without a key are scanned but stay silent.
+//!
+//! Each key has its own debounce state machine, so several keys can be held at
+//! the same time and each one reports its own events:
+//!
+//! * `Pressed` once the contact has been stable for `DEBOUNCE`
+//! * `LongPress` after the key has been held for `LONG_PRESS`
+//! * `Repeat` every `REPEAT_INTERVAL` after that, while it is still held
+//! * `Released` when the key goes up, followed by `Tap` if it was a short press
+//!
+//! While a key is down the matrix is scanned every `POLL_INTERVAL`. When every
+//! key is up, the scanner drives all the columns low and sleeps until a row
+//! input falls, so an idle keypad costs no CPU time at all.
+
+use core::sync::atomic::{AtomicU16, Ordering};
+
+use embassy_futures::select::select4;
+use embassy_rp::gpio::{Input, Level, Output, Pull};
+use embassy_rp::peripherals::{PIN_6, PIN_7, PIN_8, PIN_9, PIN_10, PIN_11, PIN_12, PIN_13};
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::channel::{Channel, Receiver};
+use embassy_time::{Duration, Instant, Timer};
+
+use crate::keymap::{self, NO_KEY};
+
+const DEBOUNCE: Duration = Duration::from_millis(20);
+const LONG_PRESS: Duration = Duration::from_millis(600);
+const REPEAT_INTERVAL: Duration = Duration::from_millis(150);
+
+// Scan period while a key is down or bouncing
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+// Events not read yet; every key typed ahead takes three of them
+const QUEUE_SIZE: usize = 32;
+
+static EVENTS: Channel<CriticalSectionRawMutex, KeyEvent, QUEUE_SIZE> = Channel::new();
+
+// One bit per key (row * 4 + column) as last seen by the scanner,
+// straight from the contacts and after debouncing
+static RAW_KEYS: AtomicU16 = AtomicU16::new(0);
+static DOWN_KEYS: AtomicU16 = AtomicU16::new(0);
+
+#[derive(Copy, Clone, PartialEq, defmt::Format)]
+pub enum KeyEvent {
+    Pressed(char),
+    Released(char),
+    // A press that ended before it became a long press
+    Tap(char),
+    LongPress(char),
+    Repeat(char),
+}
+
+#[derive(Copy, Clone)]
+struct KeyState {
+    // Last raw reading of the contact and when it last changed
+    raw: bool,
+    raw_since: Instant,
+    // Debounced state
+    down: bool,
+    down_since: Instant,
+    long_press: bool,
+    last_repeat: Instant,
+}
+
+impl KeyState {
+    const fn new() -> Self {
+        Self {
+            raw: false,
+            raw_since: Instant::from_ticks(0),
+            down: false,
+            down_since: Instant::from_ticks(0),
+            long_press: false,
+            last_repeat: Instant::from_ticks(0),
+        }
+    }
+}
+
+fn send_event(event: KeyEvent) {
+    if EVENTS.try_send(event).is_err() {
+        defmt::warn!("Keypad event queue full, {} dropped", event);
+    }
+}
+
+// Advance the state machine of one key
+fn step(state: &mut KeyState, key: char, raw: bool, now: Instant) {
+    // Positions without a key are still tracked, for teaching the layout
+    let emit = |event| {
+        if key != NO_KEY {
+            send_event(event);
+        }
+    };
+
+    if raw != state.raw {
+        state.raw = raw;
+        state.raw_since = now;
+    }
+
+    if state.raw != state.down && now.duration_since(state.raw_since) >= DEBOUNCE {
+        state.down = state.raw;
+
+        if state.down {
+            state.down_since = now;
+            state.long_press = false;
+            emit(KeyEvent::Pressed(key));
+        } else {
+            emit(KeyEvent::Released(key));
+            if !state.long_press {
+                emit(KeyEvent::Tap(key));
+            }
+        }
+    } else if state.down {
+        if !state.long_press && now.duration_since(state.down_since) >= LONG_PRESS {
+            state.long_press = true;
+            state.last_repeat = now;
+            emit(KeyEvent::LongPress(key));
+        } else if state.long_press && now.duration_since(state.last_repeat) >= REPEAT_INTERVAL {
+            state.last_repeat = now;
+            emit(KeyEvent::Repeat(key));
+        }
+    }
+}
+
+// The pins of the matrix, owned by the scanning task
+pub struct Scanner {
+    rows: [Input<'static>; 4],
+    cols: [Output<'static>; 4],
+    states: [[KeyState; 4]; 4],
+}
+
+impl Scanner {
+    // Initialize the keypad
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        p6: PIN_6,
+        p7: PIN_7,
+        p8: PIN_8,
+        p9: PIN_9,
+        p10: PIN_10,
+        p11: PIN_11,
+        p12: PIN_12,
+        p13: PIN_13,
+    ) -> Self {
+        let rows = [
+            Input::new(p6, Pull::Up),
+            Input::new(p7, Pull::Up),
+            Input::new(p8, Pull::Up),
+            Input::new(p9, Pull::Up),
+        ];
+
+        let cols = [
+            Output::new(p10, Level::High),
+            Output::new(p11, Level::High),
+            Output::new(p12, Level::High),
+            Output::new(p13, Level::High),
+        ];
+
+        Self { rows, cols, states: [[KeyState::new(); 4]; 4] }
+    }
+
+    // Scan the matrix once, sending the events it produces
+    fn scan(&mut self) {
+        let now = Instant::now();
+        let layout = keymap::layout();
+        let mut raw_keys = 0;
+        let mut down_keys = 0;
+
+        for c in 0..4 {
+            self.cols[c].set_low();
+            for r in 0..4 {
+                let raw = self.rows[r].is_low();
+                let state = &mut self.states[r][c];
+                step(state, layout[r][c], raw, now);
+
+                let bit = 1 << (r * 4 + c);
+                if state.raw {
+                    raw_keys |= bit;
+                }
+                if state.down {
+                    down_keys |= bit;
+                }
+            }
+            self.cols[c].set_high();
+        }
+
+        RAW_KEYS.store(raw_keys, Ordering::Relaxed);
+        DOWN_KEYS.store(down_keys, Ordering::Relaxed);
+    }
+
+    // Whether a key is down or bouncing
+    fn is_busy(&self) -> bool {
+        self.states.iter().flatten().any(|state| state.raw || state.down)
+    }
+
+    // Sleep until the matrix needs to be scanned again: a short while if a key
+    // is down, otherwise until a key is pressed
+    async fn wait(&mut self) {
+        if self.is_busy() {
+            Timer::after(POLL_INTERVAL).await;
+            return;
+        }
+
+        // With every column low, any key pulls its row low
+        for col in self.cols.iter_mut() {
+            col.set_low();
+        }
+
+        // A key pressed since the last scan has already made its edge
+        if self.rows.iter().all(|row| row.is_high()) {
+            let [r0, r1, r2, r3] = &mut self.rows;
+            select4(
+                r0.wait_for_falling_edge(),
+                r1.wait_for_falling_edge(),
+                r2.wait_for_falling_edge(),
+                r3.wait_for_falling_edge(),
+            )
+            .await;
+        }
+
+        for col in self.cols.iter_mut() {
+            col.set_high();
+        }
+    }
+}
+
+#[embassy_executor::task]
+pub async fn scan_task(mut scanner: Scanner) {
+    loop {
+        scanner.scan();
+        scanner.wait().await;
+    }
+}
+
+// Where the rest of the firmware reads the keypad from
+pub struct Keypad {
+    events: Receiver<'static, CriticalSectionRawMutex, KeyEvent, QUEUE_SIZE>,
+}
+
+impl Keypad {
+    pub fn new() -> Self {
+        Self { events: EVENTS.receiver() }
+    }
+
+    // The next event, if any, without waiting
+    pub fn poll(&mut self) -> Option<KeyEvent> {
+        self.events.try_receive().ok()
+    }
+
+    // Sleep until an event is waiting
+    pub async fn wait(&mut self) {
+        self.events.ready_to_receive().await;
+    }
+
+    // Check if a key was tapped, skipping every other event
+    pub fn scan(&mut self) -> Option<char> {
+        while let Some(event) = self.poll() {
+            if let KeyEvent::Tap(key) = event {
+                return Some(key);
+            }
+        }
+
+        None
+    }
+
+    // Forget the events so far, after reading the contacts directly
+    pub fn clear(&mut self) {
+        while self.poll().is_some() {}
+    }
+
+    // Positions of the matrix held down (after debouncing), one bit each,
+    // whatever key the layout puts there
+    pub fn down_positions(&self) -> u16 {
+        DOWN_KEYS.load(Ordering::Relaxed)
+    }
+
+    // Check if a key is held down right now, straight from the contact
+    pub fn is_held(&self, key: char) -> bool {
+        let raw_keys = RAW_KEYS.load(Ordering::Relaxed);
+
+        keymap::layout()
+            .iter()
+            .flatten()
+            .position(|k| *k == key)
+            .is_some_and(|i| raw_keys & (1 << i) != 0)
+    }
+}
@@ -0,0 +1,168 @@
+//! Placeholders in messages, filled in just before they are sent.
+//!
+//! A typed or stored message may hold `{CALL}` (the callsign from the
+//! settings), `{NR}` (the contest serial number, at least three digits),
+//! `{RST}` (the report from the settings) and `{TIME}` (hours and minutes since
+//! the unit started, as it has no real-time clock). Names are matched in any
+//! case. Braces have no Morse code, so anything else between them is sent
+//! without them, as is the text after a brace that is never closed. The
+//! digits of `{NR}` and `{RST}` are cut as set in the settings, so 599 can go
+//! out as 5NN.
+//!
+//! Once a message with `{NR}` has been sent in full, the serial number moves
+//! on and is saved, so the same exchange can be sent again for the next
+//! contact without retyping it.
+
+use core::fmt::Write;
+
+use embassy_time::Instant;
+use heapless::String;
+
+use crate::display::Display;
+use crate::keyer::Keyer;
+use crate::keypad::Keypad;
+use crate::settings::{self, MAX_SERIAL, Settings};
+use crate::storage::Storage;
+use crate::transmit;
+
+// Longest message once expanded: a memory sent 9 times in a row, with room
+// for its placeholders to grow
+const EXPANDED_LEN: usize = 512;
+
+// Append the value of placeholder `name` to `out`, false if it is not one
+fn placeholder<const N: usize>(name: &str, settings: &Settings, out: &mut String<N>) -> bool {
+    let mut value = String::<8>::new();
+    let mut cut = false;
+
+    if name.eq_ignore_ascii_case("CALL") {
+        value.push_str(&settings.callsign).ok();
+    } else if name.eq_ignore_ascii_case("NR") {
+        write!(value, "{:03}", settings.serial).ok();
+        cut = true;
+    } else if name.eq_ignore_ascii_case("RST") {
+        value.push_str(settings.rst()).ok();
+        cut = true;
+    } else if name.eq_ignore_ascii_case("TIME") {
+        let minutes = Instant::now().as_secs() / 60;
+        write!(value, "{:02}{:02}", minutes / 60 % 24, minutes % 60).ok();
+    } else {
+        return false;
+    }
+
+    for c in value.chars() {
+        out.push(if cut { settings.cut_digit(c) } else { c }).ok();
+    }
+    true
+}
+
+// `text` with its placeholders replaced
+pub fn expand<const N: usize>(text: &str, settings: &Settings) -> String<N> {
+    let mut out = String::new();
+    let mut rest = text;
+
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]).ok();
+        let after = &rest[start + 1..];
+
+        match after.find('}') {
+            Some(end) => {
+                let name = &after[..end];
+                if !placeholder(name, settings, &mut out) {
+                    out.push_str(name).ok();
+                }
+                rest = &after[end + 1..];
+            }
+            // A brace that is never closed is left out
+            None => {
+                rest = after;
+                break;
+            }
+        }
+    }
+
+    out.push_str(rest).ok();
+    out
+}
+
+fn uses_serial(text: &str) -> bool {
+    let mut rest = text;
+    while let Some(start) = rest.find('{') {
+        rest = &rest[start + 1..];
+        if rest.get(..3).is_some_and(|name| name.eq_ignore_ascii_case("NR}")) {
+            return true;
+        }
+    }
+    false
+}
+
+// Expand and send `text`, returns false if the user aborted it
+pub async fn send<D: Display>(
+    lcd: &mut D,
+    keypad: &mut Keypad,
+    keyer: &mut Keyer,
+    flash: &mut Storage,
+    text: &str,
+) -> bool {
+    let mut current = settings::get();
+    let expanded = expand::<EXPANDED_LEN>(text, &current);
+
+    if !transmit::send(lcd, keypad, keyer, &expanded).await {
+        return false;
+    }
+
+    if uses_serial(text) {
+        current.serial = current.serial % MAX_SERIAL + 1;
+        defmt::info!("Next serial number {}", current.serial);
+        settings::save(flash, current);
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings() -> Settings {
+        let mut settings = Settings::DEFAULT;
+        settings.callsign = String::try_from("F4ABC").unwrap();
+        settings.serial = 10;
+        settings
+    }
+
+    #[test]
+    fn expand_fills_in_placeholders() {
+        let settings = settings();
+        assert_eq!(expand::<64>("CQ DE {CALL} K", &settings).as_str(), "CQ DE F4ABC K");
+        assert_eq!(expand::<64>("{RST} {NR}", &settings).as_str(), "599 010");
+        assert_eq!(expand::<64>("{call}{Nr}", &settings).as_str(), "F4ABC010");
+    }
+
+    #[test]
+    fn expand_cuts_numbers() {
+        let mut settings = settings();
+        settings.cut = 1;
+        assert_eq!(expand::<64>("{RST} {NR}", &settings).as_str(), "599 T1T");
+        settings.cut = 2;
+        assert_eq!(expand::<64>("{RST} {NR}", &settings).as_str(), "5NN T1T");
+        // Only the numbers are cut
+        settings.callsign = String::try_from("9A0B").unwrap();
+        assert_eq!(expand::<64>("{CALL} 90", &settings).as_str(), "9A0B 90");
+    }
+
+    #[test]
+    fn expand_drops_the_braces_of_anything_else() {
+        let settings = settings();
+        assert_eq!(expand::<64>("{FOO} BAR", &settings).as_str(), "FOO BAR");
+        assert_eq!(expand::<64>("{}TEST", &settings).as_str(), "TEST");
+        assert_eq!(expand::<64>("DE {CALL", &settings).as_str(), "DE CALL");
+        assert_eq!(expand::<64>("A } B", &settings).as_str(), "A } B");
+    }
+
+    #[test]
+    fn uses_serial_looks_for_nr_only() {
+        assert!(uses_serial("5NN {NR}"));
+        assert!(uses_serial("{nr}"));
+        assert!(!uses_serial("{NRX} {CALL} NR"));
+        assert!(!uses_serial("{N"));
+    }
+}
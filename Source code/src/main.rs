@@ -1,30 +1,184 @@
-#![no_std]
-#![no_main]
+#![cfg_attr(not(test), no_std)]
+#![cfg_attr(not(test), no_main)]
+
+mod beacon;
+mod browser;
+mod display;
+mod editor;
+mod fist;
+mod keyer;
+mod keymap;
+mod keypad;
+mod macros;
+mod memories;
+mod modes;
+mod morse;
+mod predictive;
+mod reaction;
+mod settings;
+mod simon;
+mod storage;
+mod text;
+#[cfg(feature = "tft")]
+mod tft;
+mod timeline;
+mod transmit;
+mod tutorial;
+mod ui;
+
+use core::fmt::Write;
 
 use embassy_executor::Spawner;
-use embassy_rp::gpio::{Level, Output, Input, Pull};
-use embassy_rp::{init, bind_interrupts, i2c::InterruptHandler};
+use embassy_futures::select::{select, Either};
+use embassy_rp::gpio::{Input, Pull};
+use embassy_rp::init;
+#[cfg(not(feature = "tft"))]
+use embassy_rp::{bind_interrupts, i2c::InterruptHandler};
+#[cfg(not(feature = "tft"))]
 use embassy_rp::i2c::{I2c, Config as I2cConfig};
+#[cfg(not(feature = "tft"))]
 use embassy_rp::peripherals::I2C1;
-use embassy_time::{Timer, Duration, Delay, Instant};
+#[cfg(feature = "tft")]
+use embassy_rp::gpio::{Level, Output};
+#[cfg(feature = "tft")]
+use embassy_rp::spi::{Config as SpiConfig, Spi};
+#[cfg(not(feature = "tft"))]
+use embassy_time::Delay;
+use embassy_time::{Timer, Duration, Instant};
 use heapless::String;
 use {defmt_rtt as _, panic_probe as _};
+#[cfg(not(feature = "tft"))]
 use lcd1602_driver::{
-    lcd::{Basic, Ext, Lcd, Config},
+    lcd::{Lcd, Config},
     sender::I2cSender,
 };
+#[cfg(feature = "tft")]
+use display_interface_spi::SPIInterface;
+#[cfg(feature = "tft")]
+use embedded_hal_bus::spi::ExclusiveDevice;
 use rand::Rng;
 use rand::rngs::SmallRng;
 use rand::SeedableRng;
 
+use display::{Cursor, Display};
+use editor::Editor;
+use keyer::{Keyer, Transmitter};
+use keymap::{Binding, BACK};
+use keypad::{KeyEvent, Keypad, Scanner};
+use modes::Mode;
+use morse::morse_table;
+use predictive::Predictor;
+use storage::Storage;
+use text::TextBox;
+
+#[cfg(not(feature = "tft"))]
 bind_interrupts!(struct Irqs {
     I2C1_IRQ => InterruptHandler<I2C1>;
 });
 
+
 #[derive(Copy, Clone, PartialEq)]
 enum InputMode {
     Text,
     Numeric,
+    // T9-style, one press per letter
+    Predictive,
+    // Punctuation and prosigns, with multitap
+    Symbols,
+}
+
+impl InputMode {
+    // The mode '#' switches to
+    fn next(self) -> Self {
+        match self {
+            InputMode::Text => InputMode::Numeric,
+            InputMode::Numeric => InputMode::Predictive,
+            InputMode::Predictive => InputMode::Symbols,
+            InputMode::Symbols => InputMode::Text,
+        }
+    }
+
+    // Presses of the mode key from Text mode to this one
+    fn index(self) -> u8 {
+        match self {
+            InputMode::Text => 0,
+            InputMode::Numeric => 1,
+            InputMode::Predictive => 2,
+            InputMode::Symbols => 3,
+        }
+    }
+
+    // Shown in the bottom left corner of the LCD
+    fn indicator(self, case: Case) -> &'static str {
+        match self {
+            InputMode::Text => match case {
+                Case::Upper => "ABC",
+                Case::Lower => "abc",
+                Case::Shift => "Abc",
+            },
+            InputMode::Numeric => "123",
+            InputMode::Predictive => "T9",
+            InputMode::Symbols => "?!@",
+        }
+    }
+}
+
+// Case of the letters typed in Text mode; Morse itself has none, so the
+// message keeps it and only the encoder folds it
+#[derive(Copy, Clone, PartialEq)]
+enum Case {
+    // Caps lock
+    Upper,
+    Lower,
+    // The next letter upper case, then lower case
+    Shift,
+}
+
+impl Case {
+    // The case the mode key held down switches to
+    fn next(self) -> Self {
+        match self {
+            Case::Upper => Case::Lower,
+            Case::Lower => Case::Shift,
+            Case::Shift => Case::Upper,
+        }
+    }
+
+    fn apply(self, ch: char) -> char {
+        match self {
+            Case::Upper | Case::Shift => ch.to_ascii_uppercase(),
+            Case::Lower => ch.to_ascii_lowercase(),
+        }
+    }
+
+    // The case once `ch` has been typed
+    fn after(self, ch: char) -> Self {
+        if self == Case::Shift && ch.is_ascii_alphabetic() {
+            Case::Lower
+        } else {
+            self
+        }
+    }
+}
+
+// What a key press (or the multitap timeout) asks the main loop to do
+#[derive(Copy, Clone, PartialEq)]
+enum Action {
+    // A character confirmed for the message
+    Char(char),
+    // One of the commands, named after its key on the original keypad:
+    // '*', '!', '^', '(' or ')'
+    Command(char),
+    // '#' by default: switch to the next input mode (or confirm a pending character)
+    ModeSwitch,
+    // A letter key in Predictive mode
+    Compose(char),
+    // '1' by default: the next key is an editing command or opens the modes menu
+    Function,
+    // The function key held down: straight to the modes menu
+    Menu,
+    // The mode key held down in Text mode: the next letter case
+    Shift,
 }
 
 pub const FUN_FACTS: &[&str] = &[
@@ -46,108 +200,19 @@ pub const LETTERS: &[char] = &[
     'U','V','W','X','Y','Z',
 ];
 
-// Initialize the LEDs and buzzer
-fn init_leds_and_buzzer(
-    pin18: embassy_rp::peripherals::PIN_18,
-    pin19: embassy_rp::peripherals::PIN_19,
-    pin20: embassy_rp::peripherals::PIN_20,
-    pin16: embassy_rp::peripherals::PIN_16,
-) -> (Output<'static>, Output<'static>, Output<'static>, Output<'static>) {
-    let led1 = Output::new(pin18, Level::Low);
-    let led2 = Output::new(pin19, Level::Low);
-    let led3 = Output::new(pin20, Level::Low);
-    let buzzer = Output::new(pin16, Level::Low);
-
-    (led1, led2, led3, buzzer)
-}
-
-// Initialize the keypad
-fn init_keypad(
-    p6: embassy_rp::peripherals::PIN_6,
-    p7: embassy_rp::peripherals::PIN_7,
-    p8: embassy_rp::peripherals::PIN_8,
-    p9: embassy_rp::peripherals::PIN_9,
-    p10: embassy_rp::peripherals::PIN_10,
-    p11: embassy_rp::peripherals::PIN_11,
-    p12: embassy_rp::peripherals::PIN_12,
-    p13: embassy_rp::peripherals::PIN_13,
-) -> ([Input<'static>; 4], [Output<'static>; 4], [[char; 4]; 4]) {
-    let rows = [
-        Input::new(p6, Pull::Up),
-        Input::new(p7, Pull::Up),
-        Input::new(p8, Pull::Up),
-        Input::new(p9, Pull::Up),
-    ];
-
-    let cols = [
-        Output::new(p10, Level::High),
-        Output::new(p11, Level::High),
-        Output::new(p12, Level::High),
-        Output::new(p13, Level::High),
-    ];
-
-    let keys = [
-        ['1', '2', '3', '!'],
-        ['4', '5', '6', '^'],
-        ['7', '8', '9', ')'],
-        ['*', '0', '#', '('],
-    ];
-
-    (rows, cols, keys)
-}
+// Time the input mode must stay the same before it is saved in flash
+const MODE_SAVE_DELAY: Duration = Duration::from_secs(5);
 
-fn init_state() -> (usize, String<32>, Option<char>, usize, Instant, InputMode) {
+fn init_state() -> (usize, Editor, Option<char>, usize, Instant, InputMode) {
     let fact_index = 0;
-    let message = String::<32>::new();
+    let editor = Editor::new();
     let last_key = None;
     let tap_index = 0;
     let last_press_time = Instant::now();
-    let mode = InputMode::Text;
+    // The mode set in the settings, counted in presses of the mode key
+    let mode = (0..settings::get().start_mode).fold(InputMode::Text, |mode, _| mode.next());
 
-    (fact_index, message, last_key, tap_index, last_press_time, mode)
-}
-
-// Transformation of a character into Morse signals
-fn morse_table(c: char) -> Option<&'static str> {
-    match c.to_ascii_uppercase() {
-        'A' => Some(".-"),
-        'B' => Some("-..."),
-        'C' => Some("-.-."),
-        'D' => Some("-.."),
-        'E' => Some("."),
-        'F' => Some("..-."),
-        'G' => Some("--."),
-        'H' => Some("...."),
-        'I' => Some(".."),
-        'J' => Some(".---"),
-        'K' => Some("-.-"),
-        'L' => Some(".-.."),
-        'M' => Some("--"),
-        'N' => Some("-."),
-        'O' => Some("---"),
-        'P' => Some(".--."),
-        'Q' => Some("--.-"),
-        'R' => Some(".-."),
-        'S' => Some("..."),
-        'T' => Some("-"),
-        'U' => Some("..-"),
-        'V' => Some("...-"),
-        'W' => Some(".--"),
-        'X' => Some("-..-"),
-        'Y' => Some("-.--"),
-        'Z' => Some("--.."),
-        '0' => Some("-----"),
-        '1' => Some(".----"),
-        '2' => Some("..---"),
-        '3' => Some("...--"),
-        '4' => Some("....-"),
-        '5' => Some("....."),
-        '6' => Some("-...."),
-        '7' => Some("--..."),
-        '8' => Some("---.."),
-        '9' => Some("----."),
-        _ => None,
-    }
+    (fact_index, editor, last_key, tap_index, last_press_time, mode)
 }
 
 fn get_multitap_chars(key: char) -> Option<&'static [char]> {
@@ -165,80 +230,28 @@ fn get_multitap_chars(key: char) -> Option<&'static [char]> {
     }
 }
 
-async fn flash_dot(led: &mut Output<'static>, buzzer: &mut Output<'static>) {
-    led.set_high();
-    buzzer.set_high();
-    Timer::after(Duration::from_millis(200)).await;
-    led.set_low();
-    buzzer.set_low();
-}
-
-async fn flash_dash(
-    led1: &mut Output<'static>,
-    led2: &mut Output<'static>,
-    led3: &mut Output<'static>,
-    buzzer: &mut Output<'static>,
-) {
-    led1.set_high();
-    led2.set_high();
-    led3.set_high();
-    buzzer.set_high();
-    Timer::after(Duration::from_millis(600)).await;
-    led1.set_low();
-    led2.set_low();
-    led3.set_low();
-    buzzer.set_low();
-}
-
-async fn display_letter_morse(
-    c: char,
-    led1: &mut Output<'static>,
-    led2: &mut Output<'static>,
-    led3: &mut Output<'static>,
-    buzzer: &mut Output<'static>,
-) {
-    if let Some(code) = morse_table(c) {
-        for symbol in code.chars() {
-            match symbol {
-                '.' => flash_dot(led2, buzzer).await,
-                '-' => flash_dash(led1, led2, led3, buzzer).await,
-                _ => {}
-            }
-
-            // Break between signals
-            Timer::after(Duration::from_millis(200)).await;
-        }
-
-        // Break between letters
-        Timer::after(Duration::from_millis(600)).await;
+// The prosigns are '+' (AR), '=' (BT), '&' (AS) and '(' (KN)
+fn get_symbol_chars(key: char) -> Option<&'static [char]> {
+    match key {
+        '2' => Some(&['?', '.', ',', '!']),
+        '3' => Some(&['/', '=', '+', '-']),
+        '4' => Some(&['@', '&', ':', ';']),
+        '5' => Some(&['\'', '"', '(', ')']),
+        '6' => Some(&['$', '_']),
+        // For the placeholders of macros, such as {CALL}
+        '7' => Some(&['{', '}']),
+        '0' => Some(&[' ']),
+        _ => None,
     }
 }
 
-// Check if a button is pressed
-async fn scan_keypad(
-    rows: &mut [Input<'static>; 4],
-    cols: &mut [Output<'static>; 4],
-    keys: [[char; 4]; 4],
-) -> Option<char> {
-    for (c, col) in cols.iter_mut().enumerate() {
-        col.set_low();
-
-        for (r, row) in rows.iter().enumerate() {
-            if row.is_low() {
-                while row.is_low() {
-                    Timer::after(Duration::from_millis(10)).await;
-                }
-
-                Timer::after(Duration::from_millis(100)).await;
-                col.set_high();
-                return Some(keys[r][c]);
-            }
-        }
-
-        col.set_high();
+// The characters a key cycles through in the multitap modes
+fn get_mode_chars(key: char, mode: InputMode) -> Option<&'static [char]> {
+    match mode {
+        InputMode::Text => get_multitap_chars(key),
+        InputMode::Symbols => get_symbol_chars(key),
+        InputMode::Numeric | InputMode::Predictive => None,
     }
-
-    None
 }
 
 // Returns the confirmed character based on input mode and tap index
@@ -247,33 +260,33 @@ fn confirm_key(key: char, tap_index: usize, mode: InputMode) -> Option<char> {
         InputMode::Numeric => {
             if key.is_ascii_digit() {
                 Some(key)
-            } else if key == '1' {
-                Some('1')
             } else {
                 None
             }
         }
-        InputMode::Text => {
-            if let Some(chars) = get_multitap_chars(key) {
+        InputMode::Text | InputMode::Symbols => {
+            if let Some(chars) = get_mode_chars(key, mode) {
                 Some(chars[tap_index % chars.len()])
             } else {
                 None
             }
         }
+        // Words are confirmed by the predictor, not by a timeout
+        InputMode::Predictive => None,
     }
 }
 
 async fn handle_multitap_input(
-    rows: &mut [Input<'static>; 4],
-    cols: &mut [Output<'static>; 4],
-    keys: [[char; 4]; 4],
+    keypad: &mut Keypad,
     last_key: &mut Option<char>,
     tap_index: &mut usize,
     last_press_time: &mut Instant,
+    // A key tapped on the previous call and still to be handled
+    deferred: &mut Option<char>,
     mode: InputMode,
-) -> Option<(char, bool)> {
+) -> Option<Action> {
     let now = Instant::now();
-    let timeout = Duration::from_millis(1000);
+    let timeout = settings::get().multitap_timeout();
 
     // Confirm the key after timeout
     if let Some(last) = last_key {
@@ -281,49 +294,97 @@ async fn handle_multitap_input(
             if let Some(ch) = confirm_key(*last, *tap_index, mode) {
                 *last_key = None;
                 *tap_index = 0;
-                return Some((ch, false));
+                return Some(Action::Char(ch));
             }
         }
     }
 
-    // Detect the key pressed
-    if let Some(key) = scan_keypad(rows, cols, keys).await {
-        if key == '#' {
-            defmt::info!("Mode switch requested via '#'");
-            *last_key = None;
-            *tap_index = 0;
-            return Some(('#', true));
-        } else if key == '*' {
-            defmt::info!("Fun Fact key pressed: '*'");
-            *last_key = None;
-            *tap_index = 0;
-            return Some(('*', false));
-        } else if key == '!' {
-            defmt::info!("Hello key pressed: '!'");
-            *last_key = None;
-            *tap_index = 0;
-            return Some(('!', false));
-        } else if key == '(' {
-            defmt::info!("Test key pressed: '!'");
-            *last_key = None;
-            *tap_index = 0;
-            return Some(('(', false));
-        } else if key == ')' {
-            defmt::info!("Demo quiz key pressed: '!'");
-            *last_key = None;
-            *tap_index = 0;
-            return Some((')', false));
-        } else if key == '^' {
-            defmt::info!("SOS key pressed: '!'");
-            *last_key = None;
-            *tap_index = 0;
-            return Some(('^', false));
+    // Detect the key pressed, a long press on the function key opens the modes
+    // menu (the function layer in Numeric mode, where a tap types the digit)
+    let mut tapped = deferred.take();
+    while tapped.is_none() {
+        let Some(event) = keypad.poll() else { break };
+
+        match event {
+            KeyEvent::Tap(key) => {
+                tapped = Some(key);
+                break;
+            }
+            KeyEvent::LongPress(key) if keymap::binding(key) == Some(Binding::Function) => {
+                *last_key = None;
+                *tap_index = 0;
+
+                if mode == InputMode::Numeric {
+                    defmt::info!("Function layer requested via long press on '{}'", key);
+                    return Some(Action::Function);
+                }
+                defmt::info!("Menu requested via long press on '{}'", key);
+                return Some(Action::Menu);
+            }
+            // The pending character stays, in the new case
+            KeyEvent::LongPress(key)
+                if mode == InputMode::Text && keymap::binding(key) == Some(Binding::ModeSwitch) =>
+            {
+                defmt::info!("Case change requested via long press on '{}'", key);
+                return Some(Action::Shift);
+            }
+            _ => {}
+        }
+    }
+
+    if let Some(key) = tapped {
+        // In Numeric mode a digit key types its digit, unless it switches the mode
+        let binding = match keymap::binding(key) {
+            Some(Binding::ModeSwitch) => Some(Binding::ModeSwitch),
+            _ if mode == InputMode::Numeric && key.is_ascii_digit() => None,
+            binding => binding,
+        };
+
+        // The function and command keys confirm the character being tapped
+        // first, and are handled on the next call
+        if matches!(binding, Some(Binding::Function | Binding::Command(_))) {
+            if let Some(ch) = last_key.and_then(|last| confirm_key(last, *tap_index, mode)) {
+                *last_key = None;
+                *tap_index = 0;
+                *deferred = Some(key);
+                return Some(Action::Char(ch));
+            }
+        }
+
+        match binding {
+            Some(Binding::ModeSwitch) => {
+                // With a character pending, the mode key confirms it right away
+                if let Some(ch) = last_key.and_then(|last| confirm_key(last, *tap_index, mode)) {
+                    defmt::info!("Confirm requested via '{}'", key);
+                    *last_key = None;
+                    *tap_index = 0;
+                    return Some(Action::Char(ch));
+                }
+
+                defmt::info!("Mode switch requested via '{}'", key);
+                *last_key = None;
+                *tap_index = 0;
+                return Some(Action::ModeSwitch);
+            }
+            Some(Binding::Function) => {
+                defmt::info!("Function key pressed: '{}'", key);
+                *last_key = None;
+                *tap_index = 0;
+                return Some(Action::Function);
+            }
+            Some(Binding::Command(command)) => {
+                defmt::info!("Command key pressed: '{}'", key);
+                *last_key = None;
+                *tap_index = 0;
+                return Some(Action::Command(command));
+            }
+            None => {}
         }
 
         match mode {
-            InputMode::Text => {
-                if get_multitap_chars(key).is_none() {
-                    defmt::warn!("Unmapped key '{}' in Text mode", key);
+            InputMode::Text | InputMode::Symbols => {
+                if get_mode_chars(key, mode).is_none() {
+                    defmt::warn!("Unmapped key '{}' in multitap mode", key);
                     *last_key = None;
                     *tap_index = 0;
                     return None;
@@ -337,6 +398,18 @@ async fn handle_multitap_input(
                     return None;
                 }
             }
+            InputMode::Predictive => {
+                // One press per letter, the main loop looks the word up
+                if ('2'..='9').contains(&key) {
+                    defmt::info!("Predictive key: {}", key);
+                    return Some(Action::Compose(key));
+                } else if key == '0' {
+                    return Some(Action::Char(' '));
+                }
+
+                defmt::warn!("Unmapped key '{}' in Predictive mode", key);
+                return None;
+            }
         }
 
         defmt::info!("Pressed key: {}", key);
@@ -350,7 +423,7 @@ async fn handle_multitap_input(
                     *last_key = Some(key);
                     *tap_index = 0;
                     *last_press_time = now;
-                    return Some((ch, false));
+                    return Some(Action::Char(ch));
                 }
             }
             *tap_index = 0;
@@ -360,8 +433,8 @@ async fn handle_multitap_input(
         *last_press_time = now;
 
         match mode {
-            InputMode::Text => {
-                if let Some(chars) = get_multitap_chars(key) {
+            InputMode::Text | InputMode::Symbols => {
+                if let Some(chars) = get_mode_chars(key, mode) {
                     let ch = chars[*tap_index % chars.len()];
                     defmt::info!("Current character: '{}'", ch);
                 }
@@ -369,214 +442,400 @@ async fn handle_multitap_input(
             InputMode::Numeric => {
                 defmt::info!("Current digit: '{}'", key);
             }
+            InputMode::Predictive => {}
         }
 
     }
 
-    Timer::after(Duration::from_millis(50)).await;
+    // A pending character still has to time out, otherwise sleep until a key is pressed
+    if last_key.is_some() {
+        Timer::after(Duration::from_millis(10)).await;
+    } else {
+        keypad.wait().await;
+    }
     None
 }
 
 
 #[embassy_executor::main]
-async fn main(_spawner: Spawner) {
+async fn main(spawner: Spawner) {
     // Initialize the peripherals
     let p = init(Default::default());
     
-    // Initit the hardware of the project
-    let (mut led1, mut led2, mut led3, mut buzzer) = init_leds_and_buzzer(
-        p.PIN_18, p.PIN_19, p.PIN_20, p.PIN_16
-    );
-
+    // Initit the hardware of the project. The keypad and the Morse output run
+    // in their own tasks; this one keeps the LCD and the user interface.
+    let transmitter = Transmitter::new(p.PIN_18, p.PIN_19, p.PIN_20, p.PIN_16);
+    spawner.spawn(keyer::keyer_task(transmitter)).unwrap();
+    let mut keyer = Keyer::new();
 
-    let (mut row_pins, mut col_pins, keys) = init_keypad(
+    let scanner = Scanner::new(
         p.PIN_6, p.PIN_7, p.PIN_8, p.PIN_9,
         p.PIN_10, p.PIN_11, p.PIN_12, p.PIN_13,
     );
+    spawner.spawn(keypad::scan_task(scanner)).unwrap();
+    let mut keypad = Keypad::new();
+
+    // Straight key for manual keying, closes to ground
+    let straight_key = Input::new(p.PIN_14, Pull::Up);
+
+    // On-board flash, for the data kept across power cycles
+    let mut flash = Storage::new_blocking(p.FLASH);
+    let progress = storage::load(&mut flash);
+    keymap::set(progress.layout);
+    keymap::set_bindings(progress.bindings);
+    settings::set(progress.settings);
+    settings::apply(&mut keyer).await;
 
     // Initialize variables for LCD screen
+    #[cfg(not(feature = "tft"))]
     let sda = p.PIN_2;
+    #[cfg(not(feature = "tft"))]
     let scl = p.PIN_3;
+    #[cfg(not(feature = "tft"))]
     let mut i2c = I2c::new_async(p.I2C1, scl, sda, Irqs, I2cConfig::default());
-   
+
+    #[cfg(not(feature = "tft"))]
     let mut delay = Delay;
-    let mut sender = I2cSender::new(&mut i2c, 0x27);
-    let mut lcd = Lcd::new(&mut sender, &mut delay, Config::default(), None);
+    #[cfg(not(feature = "tft"))]
+    let mut sender = I2cSender::new(&mut i2c, settings::get().lcd_address);
+    #[cfg(not(feature = "tft"))]
+    let mut lcd = display::CharLcd::new(
+        Lcd::new(&mut sender, &mut delay, Config::default(), None),
+        display::LCD_SIZE,
+    );
+
+    // Or the SPI TFT, on the same corner of the board
+    #[cfg(feature = "tft")]
+    let mut lcd = {
+        let mut config = SpiConfig::default();
+        config.frequency = 32_000_000;
+        let spi = Spi::new_blocking_txonly(p.SPI0, p.PIN_2, p.PIN_3, config);
+
+        let cs = Output::new(p.PIN_5, Level::High);
+        let dc = Output::new(p.PIN_4, Level::Low);
+        let rst = Output::new(p.PIN_15, Level::High);
+
+        let device = ExclusiveDevice::new_no_delay(spi, cs);
+        let di = SPIInterface::new(device, dc);
+        tft::Tft::new(tft::panel(di, rst))
+    };
 
     // Initialization message on LCD
     Timer::after(Duration::from_millis(300)).await;
-    lcd.return_home();
-    Timer::after(Duration::from_millis(5)).await;
 
-    lcd.clean_display();
+    lcd.clear();
     Timer::after(Duration::from_millis(5)).await;
     lcd.set_cursor_pos((0, 0));
-    lcd.write_str_to_cur("Welcome to ");
+    lcd.write_str("Welcome to ");
     lcd.set_cursor_pos((0, 1));
-    lcd.write_str_to_cur("Morse Coder!");
+    lcd.write_str("Morse Coder!");
+
+    let (mut fact_index, mut editor, mut last_key, mut tap_index, mut last_press_time, mut mode) = init_state();
+    let mut deferred = None;
+    let mut mode_changed: Option<Instant> = None;
+    let mut case = Case::Upper;
+    editor.set_indicator(mode.indicator(case));
+
+    // The multitap character on screen and the cells left in its countdown
+    let mut preview: Option<(char, usize)> = None;
+
+    // The word being composed in Predictive mode
+    let mut predictor = Predictor::new();
 
-    let (mut fact_index, mut message, mut last_key, mut tap_index, mut last_press_time, mut mode) = init_state();
+    // The last character typed, its code is echoed until the next key
+    let mut echo: Option<char> = None;
 
-    macro_rules! show_char_morse {
+    // Redraw the message buffer, with a warning once it is full
+    macro_rules! show_editor {
+        () => {{
+            match echo {
+                Some(ch) if !editor.is_full() => editor.show_code(&mut lcd, ch),
+                _ => editor.show(&mut lcd, if editor.is_full() { "Full!" } else { "" }),
+            }
+        }};
+    }
+
+    // Insert a confirmed character in the message and play it
+    macro_rules! insert_char {
         ($ch:expr) => {{
-            lcd.clean_display();
-            lcd.set_cursor_pos((0, 0));
-            lcd.write_str_to_cur("Char: ");
-            lcd.write_char_to_cur($ch);
+            defmt::info!("Final confirmed input: '{}'", $ch);
 
-            if let Some(code) = morse_table($ch) {
+            if editor.insert($ch).is_err() {
+                defmt::warn!("Message buffer full, '{}' dropped", $ch);
+                lcd.clear();
+                lcd.set_cursor_pos((0, 0));
+                lcd.write_str("Buffer full!");
                 lcd.set_cursor_pos((0, 1));
-                lcd.write_str_to_cur("Morse: ");
-                lcd.write_str_to_cur(code);
-                display_letter_morse($ch, &mut led1, &mut led2, &mut led3, &mut buzzer).await;
+                lcd.write_str("Delete: 1 then *");
+                Timer::after(Duration::from_millis(1500)).await;
             } else {
-                lcd.set_cursor_pos((0, 1));
-                lcd.write_str_to_cur("Unmapped!");
-                Timer::after(Duration::from_millis(600)).await;
+                // Played in the background, the next key can already be typed
+                keyer.queue_char($ch).await;
+                echo = Some($ch);
+            }
+        }};
+    }
+
+    // Show the proposed word in place, with its rank among the candidates
+    macro_rules! show_word {
+        () => {{
+            let word = predictor.candidate();
+            let (position, count) = predictor.position();
+            let mut hint = String::<16>::new();
+            if count == 0 {
+                write!(hint, "No match").ok();
+            } else {
+                write!(hint, "#=next {}/{}", position, count).ok();
+            }
+            editor.show_pending(&mut lcd, &word, &hint);
+        }};
+    }
+
+    // Pick a mode from the menu and run it until the user comes back
+    macro_rules! run_mode {
+        () => {{
+            match modes::choose(&mut lcd, &mut keypad).await {
+                Some(Mode::Reference) => browser::run(&mut lcd, &mut keypad, &mut keyer).await,
+                Some(Mode::FistAnalyzer) => {
+                    fist::run(&mut lcd, &mut keypad, &mut keyer, &straight_key).await
+                }
+                Some(Mode::Simon) => simon::run(&mut lcd, &mut keypad, &mut keyer).await,
+                Some(Mode::SpeedTest) => {
+                    reaction::run(&mut lcd, &mut keypad, &mut keyer, &straight_key, &mut flash).await
+                }
+                Some(Mode::Tutorial) => {
+                    tutorial::run(&mut lcd, &mut keypad, &mut keyer, &mut flash).await
+                }
+                Some(Mode::Timeline) => {
+                    timeline::run(&mut lcd, &mut keypad, &mut keyer, &straight_key).await
+                }
+                Some(Mode::Beacon) => beacon::run(&mut lcd, &mut keypad, &mut keyer, &mut flash).await,
+                Some(Mode::Keymap) => keymap::run(&mut lcd, &mut keypad, &mut flash).await,
+                Some(Mode::Settings) => {
+                    settings::run(&mut lcd, &mut keypad, &mut keyer, &mut flash).await
+                }
+                None => {}
             }
         }};
     }
 
     loop {
-        if let Some((c, is_mode_switch)) = handle_multitap_input(
-            &mut row_pins,
-            &mut col_pins,
-            keys,
+        let input = handle_multitap_input(
+            &mut keypad,
             &mut last_key,
             &mut tap_index,
             &mut last_press_time,
+            &mut deferred,
             mode
-        ).await {
-            if is_mode_switch {
-                mode = match mode {
-                    InputMode::Text => InputMode::Numeric,
-                    InputMode::Numeric => InputMode::Text,
-                };
-
-                lcd.clean_display();
-                lcd.set_cursor_pos((0, 0));
-                lcd.write_str_to_cur(match mode {
-                    InputMode::Text => "Mode: Text",
-                    InputMode::Numeric => "Mode: Numeric",
-                });
-                continue;
-            }
+        );
+
+        // The mode is saved once it has stayed the same for a while, not on
+        // every press of the mode key
+        let action = match mode_changed {
+            Some(changed) => match select(input, Timer::at(changed + MODE_SAVE_DELAY)).await {
+                Either::First(action) => action,
+                Either::Second(_) => {
+                    settings::save(&mut flash, settings::get());
+                    mode_changed = None;
+                    continue;
+                }
+            },
+            None => input.await,
+        };
+
+        if let Some(action) = action {
+            // Only the editor view shows the cursor
+            lcd.set_cursor(Cursor::Hidden);
+            preview = None;
+            echo = None;
+
+            match action {
+                Action::Compose(key) => {
+                    if !predictor.push(key) {
+                        defmt::warn!("Predicted word too long, '{}' dropped", key);
+                    }
+                    show_word!();
+                    continue;
+                }
 
-            defmt::info!("Final confirmed input: '{}'", c);
-            message.push(c).ok();
+                // While a word is composed, '#' cycles through the candidates
+                Action::ModeSwitch if predictor.is_composing() => {
+                    predictor.next_candidate();
+                    show_word!();
+                    continue;
+                }
 
-            match c {
-                '*' => {
-                    let fact = FUN_FACTS[fact_index % FUN_FACTS.len()];
-                    fact_index += 1;
+                // ... and '*' takes back the last letter
+                Action::Command('*') if predictor.is_composing() => {
+                    predictor.pop();
+                    if predictor.is_composing() {
+                        show_word!();
+                    } else {
+                        show_editor!();
+                    }
+                    continue;
+                }
 
-                    lcd.clean_display();
-                    lcd.set_cursor_pos((0, 0));
-                    lcd.write_str_to_cur("Fun Fact:");
+                _ => {}
+            }
 
-                    let len = fact.len();
-                    let display_width = 16;
+            // Any other key accepts the word being composed
+            if let Some(word) = predictor.take_word() {
+                for ch in word.chars() {
+                    insert_char!(ch);
+                }
+            }
 
-                    for i in 0..=(len.saturating_sub(display_width)) {
-                        lcd.set_cursor_pos((0, 1));
-                        lcd.write_str_to_cur(&fact[i..i + display_width]);
-                        Timer::after(Duration::from_millis(600)).await;
-                    }
+            let c = match action {
+                Action::ModeSwitch => {
+                    mode = mode.next();
+                    editor.set_indicator(mode.indicator(case));
+
+                    // Remembered across power cycles, see `MODE_SAVE_DELAY`
+                    let mut current = settings::get();
+                    current.start_mode = mode.index();
+                    settings::set(current);
+                    mode_changed = Some(Instant::now());
+
+                    show_editor!();
+                    continue;
                 }
 
-                '!' => {
-                    let message = "HELLO";
-                    let mut morse_string = String::<64>::new();
+                Action::Function => {
+                    let mut hint = String::<16>::new();
+                    write!(hint, "4< 6> {}Del {}Clr", keymap::key_of(BACK), keymap::key_of(Binding::ModeSwitch)).ok();
+                    ui::show_lines(&mut lcd, &hint, "5Menu 7Save 0Mem");
+
+                    let key = ui::wait_key(&mut keypad).await;
 
-                    for ch in message.chars() {
-                        if let Some(code) = morse_table(ch) {
-                            morse_string.push_str(code).ok();
+                    // Keypads without command keys reach the commands from here
+                    if let Some(command) = keymap::function_command(key) {
+                        command
+                    } else {
+                        match key {
+                            _ if keymap::binding(key) == Some(BACK) => editor.backspace(),
+                            _ if keymap::binding(key) == Some(Binding::ModeSwitch) => editor.clear(),
+                            '4' => editor.left(),
+                            '6' => editor.right(),
+                            '1' => insert_char!('1'),
+                            '5' => run_mode!(),
+                            '7' => {
+                                if let Some(index) = memories::choose(&mut lcd, &mut keypad, "Save to memory").await {
+                                    if editor.is_empty() {
+                                        // Nothing typed: bring the memory back to change it
+                                        editor.clear();
+                                        for ch in memories::load(&mut flash, index).text.chars() {
+                                            editor.insert(ch).ok();
+                                        }
+                                    } else {
+                                        memories::store(&mut lcd, &mut keypad, &mut flash, index, editor.chars()).await;
+                                    }
+                                }
+                            }
+                            '0' => {
+                                if let Some(index) = memories::choose(&mut lcd, &mut keypad, "Send memory").await {
+                                    let memory = memories::load(&mut flash, index);
+                                    let result = if memory.is_empty() {
+                                        "Memory is empty"
+                                    } else if memories::send(&mut lcd, &mut keypad, &mut keyer, &mut flash, index, &memory).await {
+                                        "Memory sent!"
+                                    } else {
+                                        "Aborted"
+                                    };
+
+                                    lcd.clear();
+                                    lcd.set_cursor_pos((0, 0));
+                                    lcd.write_str(result);
+                                    Timer::after(Duration::from_millis(1000)).await;
+                                }
+                            }
+                            _ => {}
                         }
+
+                        show_editor!();
+                        continue;
                     }
+                }
 
-                    lcd.clean_display();
+                Action::Menu => {
+                    run_mode!();
+                    show_editor!();
+                    continue;
+                }
+
+                Action::Char(c) => {
+                    let c = if mode == InputMode::Text {
+                        let c = case.apply(c);
+                        case = case.after(c);
+                        editor.set_indicator(mode.indicator(case));
+                        c
+                    } else {
+                        c
+                    };
+
+                    insert_char!(c);
+                    show_editor!();
+                    continue;
+                }
+
+                Action::Shift => {
+                    case = case.next();
+                    editor.set_indicator(mode.indicator(case));
+                    show_editor!();
+                    continue;
+                }
+
+                Action::Command(c) => c,
+
+                Action::Compose(_) => continue,
+            };
+
+            match c {
+                '*' => {
+                    let fact = FUN_FACTS[fact_index % FUN_FACTS.len()];
+                    fact_index += 1;
+
+                    lcd.clear();
                     lcd.set_cursor_pos((0, 0));
-                    lcd.write_str_to_cur("Msg: HELLO");
-                    lcd.set_cursor_pos((0, 1));
-                    lcd.write_str_to_cur(&morse_string);
+                    lcd.write_str("Fun Fact:");
 
-                    for symbol in morse_string.chars() {
-                        match symbol {
-                            '.' => flash_dot(&mut led2, &mut buzzer).await,
-                            '-' => flash_dash(&mut led1, &mut led2, &mut led3, &mut buzzer).await,
-                            _ => {}
-                        }
+                    let marquee = TextBox::below(&lcd, 1).speed(settings::get().scroll_speed());
+                    marquee.play(&mut lcd, fact).await;
+                }
 
-                        Timer::after(Duration::from_millis(200)).await;
-                    }
+                '!' => {
+                    let sent = transmit::send(&mut lcd, &mut keypad, &mut keyer, "HELLO").await;
 
-                    lcd.clean_display();
+                    lcd.clear();
                     lcd.set_cursor_pos((0, 0));
-                    lcd.write_str_to_cur("HELLO sent!");
+                    lcd.write_str(if sent { "HELLO sent!" } else { "Aborted" });
                     Timer::after(Duration::from_secs(1)).await;
                 }
 
                 '(' => {
-                    if message.is_empty() {
-                        lcd.clean_display();
+                    if editor.is_empty() {
+                        lcd.clear();
                         lcd.set_cursor_pos((0, 0));
-                        lcd.write_str_to_cur("No msg to send");
+                        lcd.write_str("No msg to send");
                         Timer::after(Duration::from_millis(1000)).await;
                     } else {
-                        let filtered_chars = message
-                            .chars()
-                            .take(message.len().saturating_sub(1))
-                            .filter(|ch| !matches!(ch, '*' | '!' | '(' | ')' | '^'));
-
-                        lcd.clean_display();
-
-                        // Print the message on the first line
-                        lcd.set_cursor_pos((0, 0));
-                        let mut count = 0;
-                        for ch in filtered_chars {
-                            if count < 16 {
-                                lcd.write_char_to_cur(ch);
-                                count += 1;
-                            } else {
-                                break;
-                            }
+                        let mut message = String::<{ editor::CAPACITY * 4 }>::new();
+                        for ch in editor.chars() {
+                            message.push(ch).ok();
                         }
 
-                        // Print each letter's Morse codification
-                        for ch in message
-                            .chars()
-                            .take(message.len().saturating_sub(1))
-                            .filter(|ch| !matches!(ch, '*' | '!' | '(' | ')' | '^'))
-                        {
-                            if let Some(code) = morse_table(ch) {
-                                lcd.set_cursor_pos((0, 1));
-                                lcd.write_str_to_cur("Morse:          ");
-                                lcd.set_cursor_pos((7, 1));
-                                lcd.write_str_to_cur(code);
-
-                                for symbol in code.chars() {
-                                    match symbol {
-                                        '.' => flash_dot(&mut led2, &mut buzzer).await,
-                                        '-' => flash_dash(&mut led1, &mut led2, &mut led3, &mut buzzer).await,
-                                        _ => {}
-                                    }
-                                    Timer::after(Duration::from_millis(200)).await;
-                                }
+                        let sent = macros::send(&mut lcd, &mut keypad, &mut keyer, &mut flash, &message).await;
 
-                                Timer::after(Duration::from_millis(600)).await;
-                            } else {
-                                lcd.set_cursor_pos((0, 1));
-                                lcd.write_str_to_cur("Unmapped!");
-                                Timer::after(Duration::from_millis(600)).await;
-                            }
-                        }
-
-                        lcd.clean_display();
+                        lcd.clear();
                         lcd.set_cursor_pos((0, 0));
-                        lcd.write_str_to_cur("Done sending!");
+                        if sent {
+                            lcd.write_str("Done sending!");
+                            editor.clear();
+                        } else {
+                            // Keep the message so it can be fixed and sent again
+                            lcd.write_str("Aborted");
+                        }
                         Timer::after(Duration::from_millis(1000)).await;
-
-                        message.clear();
                     }
                 }
 
@@ -585,68 +844,68 @@ async fn main(_spawner: Spawner) {
                     let mut rng = SmallRng::seed_from_u64(ticks as u64);
                     let letter = LETTERS[rng.gen_range(0..LETTERS.len())];
 
-                    lcd.clean_display();
-                    lcd.set_cursor_pos((0, 0));
-                    lcd.write_str_to_cur("Guess the letter");
-                    lcd.set_cursor_pos((0, 1));
-                    lcd.write_str_to_cur("codified!");
+                    TextBox::screen(&lcd).show_page(&mut lcd, "Guess the letter codified!", 0);
 
                     Timer::after(Duration::from_secs(2)).await;
 
                     if morse_table(letter).is_some() {
-                        display_letter_morse(letter, &mut led1, &mut led2, &mut led3, &mut buzzer).await;
+                        keyer.play_char(letter).await;
                     }
 
                     Timer::after(Duration::from_secs(4)).await;
 
-                    lcd.clean_display();
+                    lcd.clear();
                     lcd.set_cursor_pos((0, 0));
-                    lcd.write_str_to_cur("The letter was:");
+                    lcd.write_str("The letter was:");
                     lcd.set_cursor_pos((0, 1));
-                    lcd.write_char_to_cur(letter);
+                    lcd.write_char(letter);
 
                     Timer::after(Duration::from_secs(2)).await;
                 }
 
                 '^' => {
-                    let message = "SOS";
-                    let mut morse_string = String::<32>::new();
+                    let sent = transmit::send(&mut lcd, &mut keypad, &mut keyer, "SOS").await;
 
-                    for ch in message.chars() {
-                        if let Some(code) = morse_table(ch) {
-                            morse_string.push_str(code).ok();
-                        }
-                    }
-
-                    lcd.clean_display();
+                    lcd.clear();
                     lcd.set_cursor_pos((0, 0));
-                    lcd.write_str_to_cur("Msg: SOS");
-                    lcd.set_cursor_pos((0, 1));
-                    lcd.write_str_to_cur(&morse_string);
-
-                    for symbol in morse_string.chars() {
-                        match symbol {
-                            '.' => flash_dot(&mut led2, &mut buzzer).await,
-                            '-' => flash_dash(&mut led1, &mut led2, &mut led3, &mut buzzer).await,
-                            _ => {}
-                        }
-
-                        Timer::after(Duration::from_millis(200)).await;
+                    if sent {
+                        lcd.write_str("S.O.S message");
+                        lcd.set_cursor_pos((0, 1));
+                        lcd.write_str("sent!");
+                    } else {
+                        lcd.write_str("Aborted");
                     }
+                    Timer::after(Duration::from_secs(1)).await;
+                }
 
-                    lcd.clean_display();
-                    lcd.set_cursor_pos((0, 0));
-                    lcd.write_str_to_cur("S.O.S message");
+                _ => {}
+            }
 
-                    lcd.set_cursor_pos((0, 1));
-                    lcd.write_str_to_cur("sent!");
-                    Timer::after(Duration::from_secs(1)).await;
+            show_editor!();
+        }
+
+        // Preview the character being multitapped and count down to its confirmation
+        match last_key.and_then(|key| confirm_key(key, tap_index, mode)) {
+            Some(ch) => {
+                let ch = if mode == InputMode::Text { case.apply(ch) } else { ch };
+                let elapsed = Instant::now().duration_since(last_press_time).as_millis();
+                let timeout = settings::get().multitap_timeout().as_millis();
+                let left = timeout.saturating_sub(elapsed);
+                let cells = left.div_ceil(timeout / editor::COUNTDOWN_CELLS as u64) as usize;
+
+                if preview.map(|(shown, _)| shown) != Some(ch) {
+                    let mut text = [0u8; 4];
+                    editor.show_pending(&mut lcd, ch.encode_utf8(&mut text), "#=OK");
+                    editor.show_countdown(&mut lcd, editor::COUNTDOWN_CELLS);
+                    preview = Some((ch, editor::COUNTDOWN_CELLS));
                 }
 
-                _ => {
-                    show_char_morse!(c);
+                if preview.map(|(_, shown)| shown) != Some(cells) {
+                    editor.show_countdown(&mut lcd, cells);
+                    preview = Some((ch, cells));
                 }
             }
+            None => preview = None,
         }
     }
 
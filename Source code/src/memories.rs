@@ -0,0 +1,214 @@
+//! Message memories M1 to M8, as on contest keyers.
+//!
+//! A memory is composed in the message editor like any other message, then
+//! stored with '1' '7' and its number; '1' '7' and the number of a memory with
+//! the editor empty brings it back into the editor to change it. '1' '0' and
+//! the number sends it. Each memory keeps how many times the text is sent in a
+//! row and, optionally, a delay after which it is sent again until the user
+//! stops it, to call CQ unattended.
+
+use core::fmt::Write;
+
+use embassy_futures::select::{select, Either};
+use embassy_time::{Duration, Instant, Timer};
+use heapless::String;
+
+use crate::display::Display;
+use crate::editor;
+use crate::keyer::Keyer;
+use crate::keypad::Keypad;
+use crate::storage::{self, Storage};
+use crate::macros;
+use crate::ui::{show_lines, wait_key, wait_key_repeating};
+
+pub const MEMORIES: usize = 8;
+
+// Bytes of a memory in flash: repeat count, loop delay, then the text
+pub const MEMORY_SIZE: usize = 2 + editor::CAPACITY;
+
+const MAX_REPEAT: u8 = 9;
+
+// Delays offered between two loops, in seconds, 0 for no loop
+const LOOP_DELAYS: [u8; 8] = [0, 5, 10, 15, 20, 30, 45, 60];
+
+#[derive(Clone)]
+pub struct Memory {
+    pub text: String<{ editor::CAPACITY }>,
+    // Times the text is sent in a row, separated by a word space
+    pub repeat: u8,
+    // Seconds to wait before sending it all again, 0 to send it only once
+    pub loop_delay_s: u8,
+}
+
+impl Memory {
+    pub const EMPTY: Self = Self { text: String::new(), repeat: 1, loop_delay_s: 0 };
+
+    pub fn is_empty(&self) -> bool {
+        self.text.is_empty()
+    }
+
+    pub fn to_bytes(&self) -> ([u8; MEMORY_SIZE], usize) {
+        let mut bytes = [0u8; MEMORY_SIZE];
+
+        bytes[0] = self.repeat;
+        bytes[1] = self.loop_delay_s;
+        bytes[2..2 + self.text.len()].copy_from_slice(self.text.as_bytes());
+
+        (bytes, 2 + self.text.len())
+    }
+
+    // A memory read back from flash, None if it does not check out
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let (&[repeat, loop_delay_s], text) = bytes.split_first_chunk::<2>()?;
+        if !(1..=MAX_REPEAT).contains(&repeat) || !LOOP_DELAYS.contains(&loop_delay_s) {
+            return None;
+        }
+
+        let text = core::str::from_utf8(text).ok()?;
+        Some(Self { text: String::try_from(text).ok()?, repeat, loop_delay_s })
+    }
+}
+
+// Ask for the number of a memory, None if the user cancelled with '*'
+pub async fn choose<D: Display>(lcd: &mut D, keypad: &mut Keypad, title: &str) -> Option<usize> {
+    show_lines(lcd, title, "M1-8  *=cancel");
+
+    loop {
+        match wait_key(keypad).await {
+            key @ '1'..='8' => return Some(key as usize - '1' as usize),
+            '*' => return None,
+            _ => {}
+        }
+    }
+}
+
+pub fn load(flash: &mut Storage, index: usize) -> Memory {
+    storage::load(flash).memories[index].clone()
+}
+
+// Pick the repeat count and loop delay of memory `index`, false if cancelled
+async fn edit_options<D: Display>(lcd: &mut D, keypad: &mut Keypad, index: usize, memory: &mut Memory) -> bool {
+    let mut top = String::<16>::new();
+    write!(top, "M{} repeat", index + 1).ok();
+
+    loop {
+        let mut line = String::<16>::new();
+        write!(line, "< {}x >  5=next", memory.repeat).ok();
+        show_lines(lcd, &top, &line);
+
+        match wait_key_repeating(keypad, &['2', '8']).await {
+            '2' => memory.repeat = (memory.repeat % MAX_REPEAT) + 1,
+            '8' => memory.repeat = (memory.repeat + MAX_REPEAT - 2) % MAX_REPEAT + 1,
+            '5' => break,
+            '*' => return false,
+            _ => {}
+        }
+    }
+
+    top.clear();
+    write!(top, "M{} loop", index + 1).ok();
+    let mut delay = LOOP_DELAYS.iter().position(|d| *d == memory.loop_delay_s).unwrap_or(0);
+
+    loop {
+        let mut line = String::<16>::new();
+        match LOOP_DELAYS[delay] {
+            0 => write!(line, "< off >  5=save").ok(),
+            s => write!(line, "< {}s > 5=save", s).ok(),
+        };
+        show_lines(lcd, &top, &line);
+
+        match wait_key_repeating(keypad, &['2', '8']).await {
+            '2' => delay = (delay + 1) % LOOP_DELAYS.len(),
+            '8' => delay = (delay + LOOP_DELAYS.len() - 1) % LOOP_DELAYS.len(),
+            '5' => break,
+            '*' => return false,
+            _ => {}
+        }
+    }
+
+    memory.loop_delay_s = LOOP_DELAYS[delay];
+    true
+}
+
+// Store `text` in memory `index`, once the user has picked its options
+pub async fn store<D: Display>(
+    lcd: &mut D,
+    keypad: &mut Keypad,
+    flash: &mut Storage,
+    index: usize,
+    text: impl Iterator<Item = char>,
+) {
+    let mut progress = storage::load(flash);
+    let mut memory = progress.memories[index].clone();
+
+    memory.text.clear();
+    for c in text {
+        memory.text.push(c).ok();
+    }
+
+    if !edit_options(lcd, keypad, index, &mut memory).await {
+        return;
+    }
+
+    progress.memories[index] = memory;
+    storage::save(flash, &progress);
+
+    defmt::info!("Message stored in M{}", index + 1);
+    let mut top = String::<16>::new();
+    write!(top, "Saved in M{}", index + 1).ok();
+    show_lines(lcd, &top, "");
+    Timer::after(Duration::from_millis(1000)).await;
+}
+
+// Wait `seconds` before the next loop, showing the time left; false if the
+// user stopped the loop
+async fn wait_loop<D: Display>(lcd: &mut D, keypad: &mut Keypad, index: usize, seconds: u8) -> bool {
+    // Fixed deadlines, so that the keys pressed meanwhile do not stretch the wait
+    let mut next = Instant::now();
+
+    for left in (1..=seconds).rev() {
+        let mut top = String::<16>::new();
+        write!(top, "M{} again in {}s", index + 1, left).ok();
+        show_lines(lcd, &top, "*=stop");
+
+        next += Duration::from_secs(1);
+        loop {
+            match select(wait_key(keypad), Timer::at(next)).await {
+                Either::First('*') => return false,
+                Either::First(_) => {}
+                Either::Second(_) => break,
+            }
+        }
+    }
+    true
+}
+
+// Send memory `index` with its repeats and loops, returns false if the user
+// aborted it
+pub async fn send<D: Display>(
+    lcd: &mut D,
+    keypad: &mut Keypad,
+    keyer: &mut Keyer,
+    flash: &mut Storage,
+    index: usize,
+    memory: &Memory,
+) -> bool {
+    let mut text = String::<{ (editor::CAPACITY + 1) * MAX_REPEAT as usize }>::new();
+    for i in 0..memory.repeat {
+        if i > 0 {
+            text.push(' ').ok();
+        }
+        text.push_str(&memory.text).ok();
+    }
+
+    loop {
+        // Expanded for every loop, so each one gets the next serial number
+        if !macros::send(lcd, keypad, keyer, flash, &text).await {
+            return false;
+        }
+
+        if memory.loop_delay_s == 0 || !wait_loop(lcd, keypad, index, memory.loop_delay_s).await {
+            return true;
+        }
+    }
+}
@@ -0,0 +1,54 @@
+//! Menu of the extra modes, opened with '1' then '5' from the editor, or by
+//! holding '1'.
+//!
+//! '2' and '8' move through the list, '5' opens the selected mode and '*'
+//! closes the menu.
+
+use crate::display::Display;
+use crate::keypad::Keypad;
+use crate::ui::{show_lines, show_menu, wait_key};
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum Mode {
+    Beacon,
+    FistAnalyzer,
+    Keymap,
+    Reference,
+    Settings,
+    Simon,
+    SpeedTest,
+    Timeline,
+    Tutorial,
+}
+
+pub const MODES: &[(Mode, &str)] = &[
+    (Mode::Tutorial, "Tutorial"),
+    (Mode::Reference, "Morse table"),
+    (Mode::SpeedTest, "Speed test"),
+    (Mode::Simon, "Simon says"),
+    (Mode::FistAnalyzer, "Fist analyzer"),
+    (Mode::Timeline, "Keying timeline"),
+    (Mode::Beacon, "Beacon"),
+    (Mode::Keymap, "Keypad layout"),
+    (Mode::Settings, "Settings"),
+];
+
+// Let the user pick a mode, None if the menu was closed
+pub async fn choose<D: Display>(lcd: &mut D, keypad: &mut Keypad) -> Option<Mode> {
+    let mut selected = 0;
+
+    loop {
+        show_menu(lcd, MODES.len(), |i| MODES[i].1, selected);
+
+        match wait_key(keypad).await {
+            '2' => selected = (selected + MODES.len() - 1) % MODES.len(),
+            '8' => selected = (selected + 1) % MODES.len(),
+            '5' => return Some(MODES[selected].0),
+            '*' => {
+                show_lines(lcd, "", "");
+                return None;
+            }
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,163 @@
+//! The Morse alphabet and the lookups used to encode and decode it.
+
+// Every character the device knows, paired with its Morse codification
+pub const MORSE_TABLE: &[(char, &str)] = &[
+    ('A', ".-"),
+    ('B', "-..."),
+    ('C', "-.-."),
+    ('D', "-.."),
+    ('E', "."),
+    ('F', "..-."),
+    ('G', "--."),
+    ('H', "...."),
+    ('I', ".."),
+    ('J', ".---"),
+    ('K', "-.-"),
+    ('L', ".-.."),
+    ('M', "--"),
+    ('N', "-."),
+    ('O', "---"),
+    ('P', ".--."),
+    ('Q', "--.-"),
+    ('R', ".-."),
+    ('S', "..."),
+    ('T', "-"),
+    ('U', "..-"),
+    ('V', "...-"),
+    ('W', ".--"),
+    ('X', "-..-"),
+    ('Y', "-.--"),
+    ('Z', "--.."),
+    ('0', "-----"),
+    ('1', ".----"),
+    ('2', "..---"),
+    ('3', "...--"),
+    ('4', "....-"),
+    ('5', "....."),
+    ('6', "-...."),
+    ('7', "--..."),
+    ('8', "---.."),
+    ('9', "----."),
+    ('.', ".-.-.-"),
+    (',', "--..--"),
+    ('?', "..--.."),
+    ('\'', ".----."),
+    ('!', "-.-.--"),
+    ('/', "-..-."),
+    ('(', "-.--."),
+    (')', "-.--.-"),
+    ('&', ".-..."),
+    (':', "---..."),
+    (';', "-.-.-."),
+    ('=', "-...-"),
+    ('+', ".-.-."),
+    ('-', "-....-"),
+    ('_', "..--.-"),
+    ('"', ".-..-."),
+    ('$', "...-..-"),
+    ('@', ".--.-."),
+];
+
+// Procedural signs sent as one character, with the punctuation that shares their code
+const PROSIGNS: &[(char, &str)] = &[('+', "AR"), ('=', "BT"), ('&', "AS"), ('(', "KN")];
+
+// Transformation of a character, in either case, into Morse signals
+pub fn morse_table(c: char) -> Option<&'static str> {
+    let c = c.to_ascii_uppercase();
+
+    MORSE_TABLE
+        .iter()
+        .find(|(ch, _)| *ch == c)
+        .map(|(_, code)| *code)
+}
+
+// Transformation of Morse signals back into a character
+pub fn decode(code: &str) -> Option<char> {
+    MORSE_TABLE
+        .iter()
+        .find(|(_, c)| *c == code)
+        .map(|(ch, _)| *ch)
+}
+
+// Length of a codification in dots, counting the breaks between its signals
+pub fn units(code: &str) -> u32 {
+    let signals: u32 = code.chars().map(|s| if s == '-' { 3 } else { 1 }).sum();
+    signals + code.len().saturating_sub(1) as u32
+}
+
+// Memory aids for the letters: capital syllables are dashes, the others dots
+pub const MNEMONICS: &[(char, &str)] = &[
+    ('A', "a-PART"),
+    ('B', "BOIS-ter-ous-ly"),
+    ('C', "CA-ro-LI-na"),
+    ('D', "DAN-ger-ous"),
+    ('E', "eh"),
+    ('F', "fee-fi-FO-fum"),
+    ('G', "GOV-ERN-ment"),
+    ('H', "hip-pi-ty-hop"),
+    ('I', "i-vy"),
+    ('J', "a-WHOLE-LOT-MORE"),
+    ('K', "KAN-ga-ROO"),
+    ('L', "le-MO-na-de"),
+    ('M', "MOO-MOO"),
+    ('N', "NA-vy"),
+    ('O', "OH-MY-GOD"),
+    ('P', "a-POL-LO-ni"),
+    ('Q', "GOD-SAVE-a-KING"),
+    ('R', "ro-TA-tion"),
+    ('S', "si-si-si"),
+    ('T', "TALL"),
+    ('U', "un-i-FORM"),
+    ('V', "vic-to-ry-VEE"),
+    ('W', "a-WILD-WOLF"),
+    ('X', "X-marks-the-SPOT"),
+    ('Y', "YEL-low-YO-YO"),
+    ('Z', "ZOO-LOOK-ing-up"),
+];
+
+pub fn mnemonic(c: char) -> Option<&'static str> {
+    let c = c.to_ascii_uppercase();
+
+    MNEMONICS
+        .iter()
+        .find(|(ch, _)| *ch == c)
+        .map(|(_, phrase)| *phrase)
+}
+
+// The procedural sign sent with a character, if it stands for one
+pub fn prosign(c: char) -> Option<&'static str> {
+    PROSIGNS
+        .iter()
+        .find(|(ch, _)| *ch == c)
+        .map(|(_, name)| *name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_reverses_the_table() {
+        for (c, code) in MORSE_TABLE {
+            assert_eq!(decode(code), Some(*c));
+        }
+    }
+
+    #[test]
+    fn decode_rejects_unknown_codes() {
+        assert_eq!(decode(""), None);
+        assert_eq!(decode("........"), None);
+        assert_eq!(decode("SOS"), None);
+    }
+
+    #[test]
+    fn units_count_signals_and_breaks() {
+        assert_eq!(units(""), 0);
+        assert_eq!(units("."), 1);
+        assert_eq!(units("-"), 3);
+        assert_eq!(units(".-"), 5);
+        // PARIS without its letter and word breaks: 50 - 4 * 3 - 7
+        let paris: u32 = "PARIS".chars().map(|c| units(morse_table(c).unwrap())).sum();
+        assert_eq!(paris, 31);
+    }
+}
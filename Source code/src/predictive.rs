@@ -0,0 +1,139 @@
+//! T9-style predictive text: one key press per letter.
+//!
+//! The keys pressed so far form a digit sequence; the candidates are the
+//! words of the dictionary spelled with the same keys, most frequent first.
+//! The dictionary is a single space separated string, so it stays in flash.
+
+use heapless::{String, Vec};
+
+use crate::get_multitap_chars;
+
+// Longest word that can be composed
+pub const MAX_WORD: usize = 16;
+
+// Candidates offered for one key sequence
+const MAX_CANDIDATES: usize = 8;
+
+// Common English words and radio jargon, most frequent first
+const WORDS: &str = "\
+THE OF AND TO IN IS YOU THAT IT HE WAS FOR ON ARE AS WITH HIS THEY AT BE THIS \
+HAVE FROM OR ONE HAD BY WORD BUT NOT WHAT ALL WERE WE WHEN YOUR CAN SAID THERE \
+USE AN EACH WHICH SHE DO HOW THEIR IF WILL UP OTHER ABOUT OUT MANY THEN THEM \
+THESE SO SOME HER WOULD MAKE LIKE HIM INTO TIME HAS LOOK TWO MORE WRITE GO SEE \
+NUMBER NO WAY COULD PEOPLE MY THAN FIRST WATER BEEN CALL WHO OIL ITS NOW FIND \
+LONG DOWN DAY DID GET COME MADE MAY PART OVER NEW SOUND TAKE ONLY LITTLE WORK \
+KNOW PLACE YEAR LIVE ME BACK GIVE MOST VERY AFTER THING OUR JUST NAME GOOD \
+SENTENCE MAN THINK SAY GREAT WHERE HELP THROUGH MUCH BEFORE LINE RIGHT TOO MEAN \
+OLD ANY SAME TELL BOY FOLLOW CAME WANT SHOW ALSO AROUND FORM THREE SMALL SET PUT \
+END DOES ANOTHER WELL LARGE MUST BIG EVEN SUCH BECAUSE TURN HERE WHY ASK WENT \
+MEN READ NEED LAND DIFFERENT HOME US MOVE TRY KIND HAND PICTURE AGAIN CHANGE OFF \
+PLAY SPELL AIR AWAY ANIMAL HOUSE POINT PAGE LETTER MOTHER ANSWER FOUND STUDY \
+STILL LEARN SHOULD WORLD HIGH EVERY NEAR ADD FOOD BETWEEN OWN BELOW COUNTRY \
+PLANT LAST SCHOOL FATHER KEEP TREE NEVER START CITY EARTH EYE LIGHT THOUGHT HEAD \
+UNDER STORY SAW LEFT FEW WHILE ALONG MIGHT CLOSE SOMETHING SEEM NEXT HARD OPEN \
+EXAMPLE BEGIN LIFE ALWAYS THOSE BOTH PAPER TOGETHER GOT GROUP OFTEN RUN \
+HELLO HI YES OK THANKS PLEASE SORRY BYE LOVE FRIEND MORNING NIGHT TODAY TOMORROW \
+MORSE CODE RADIO SIGNAL MESSAGE SEND KEY DOT DASH BEACON ANTENNA CALLSIGN \
+CQ DE SOS QTH QSL QRZ QSO QRM QRN QSB QRP QRT RST TNX TU FB ES HR UR OM YL \
+NAME RIG WX ANT PWR AGN PSE CUL GM GA GE GN BK AR SK KN \
+";
+
+// The key that carries a letter
+fn key_for(letter: char) -> Option<char> {
+    ('2'..='9').find(|key| get_multitap_chars(*key).is_some_and(|chars| chars.contains(&letter)))
+}
+
+// Whether the word starts with the letters of the keys pressed so far
+fn spelled_by(word: &str, digits: &str) -> bool {
+    word.len() >= digits.len()
+        && word.chars().zip(digits.chars()).all(|(letter, key)| key_for(letter) == Some(key))
+}
+
+pub struct Predictor {
+    digits: String<MAX_WORD>,
+    // Which of the candidates is shown
+    choice: usize,
+}
+
+impl Predictor {
+    pub const fn new() -> Self {
+        Self { digits: String::new(), choice: 0 }
+    }
+
+    pub fn is_composing(&self) -> bool {
+        !self.digits.is_empty()
+    }
+
+    // Add the key of the next letter, false if the word is already at its longest
+    pub fn push(&mut self, key: char) -> bool {
+        self.choice = 0;
+        self.digits.push(key).is_ok()
+    }
+
+    // Remove the last letter
+    pub fn pop(&mut self) {
+        self.choice = 0;
+        self.digits.pop();
+    }
+
+    pub fn next_candidate(&mut self) {
+        self.choice += 1;
+    }
+
+    // Words spelled exactly by the keys come first, then the beginnings of longer ones
+    fn candidates(&self) -> Vec<&'static str, MAX_CANDIDATES> {
+        let len = self.digits.len();
+        let words = WORDS.split(' ').filter(|word| spelled_by(word, &self.digits));
+        let exact = words.clone().filter(|word| word.len() == len);
+        let longer = words.filter(|word| word.len() > len).map(|word| &word[..len]);
+
+        let mut candidates = Vec::new();
+        for word in exact.chain(longer) {
+            if !candidates.contains(&word) && candidates.push(word).is_err() {
+                break;
+            }
+        }
+
+        candidates
+    }
+
+    // The word currently proposed
+    pub fn candidate(&self) -> String<MAX_WORD> {
+        let mut word = String::new();
+        let candidates = self.candidates();
+
+        match candidates.get(self.choice % candidates.len().max(1)) {
+            Some(found) => {
+                word.push_str(found).ok();
+            }
+            // Nothing in the dictionary: fall back to the first letter of every key
+            None => {
+                for key in self.digits.chars() {
+                    if let Some(chars) = get_multitap_chars(key) {
+                        word.push(chars[0]).ok();
+                    }
+                }
+            }
+        }
+
+        word
+    }
+
+    // Position of the proposed word among the candidates, and their number
+    pub fn position(&self) -> (usize, usize) {
+        let count = self.candidates().len();
+        (self.choice % count.max(1) + 1, count)
+    }
+
+    // Accept the proposed word and start a new one
+    pub fn take_word(&mut self) -> Option<String<MAX_WORD>> {
+        if !self.is_composing() {
+            return None;
+        }
+
+        let word = self.candidate();
+        self.digits.clear();
+        self.choice = 0;
+        Some(word)
+    }
+}
@@ -0,0 +1,221 @@
+//! Reaction and speed test.
+//!
+//! A random character is shown on the LCD and the user keys it as fast as
+//! possible, either on the keypad ('4' is a dot, '6' a dash) or on the straight
+//! key. A round is ten characters; its effective speed goes into a high-score
+//! table kept in flash.
+
+use core::fmt::Write;
+
+use embassy_rp::gpio::Input;
+use embassy_time::{Duration, Instant, Timer};
+use heapless::String;
+use rand::rngs::SmallRng;
+use rand::{Rng, SeedableRng};
+
+use crate::display::Display;
+use crate::keyer::Keyer;
+use crate::keypad::Keypad;
+use crate::morse::{morse_table, units};
+use crate::settings;
+use crate::storage::{self, HIGH_SCORES, Progress, Storage};
+use crate::text::TextBox;
+use crate::ui::{show_lines, wait_key};
+use crate::LETTERS;
+
+// Characters in a round
+const ROUND: usize = 10;
+
+// Marks on the straight key longer than two dots are dashes
+const DASH_THRESHOLD_MS: u64 = 400;
+
+// Edges closer than this to the previous one are contact bounce
+const DEBOUNCE_MS: u64 = 5;
+
+enum Event {
+    Element(char),
+    Exit,
+}
+
+// Turns the keypad and the straight key into a stream of dots and dashes
+struct ElementReader {
+    down: bool,
+    last_edge: Instant,
+}
+
+impl ElementReader {
+    fn new() -> Self {
+        Self { down: false, last_edge: Instant::now() }
+    }
+
+    async fn next(
+        &mut self,
+        keypad: &mut Keypad,
+        keyer: &mut Keyer,
+        straight_key: &Input<'static>,
+    ) -> Event {
+        loop {
+            let is_down = straight_key.is_low();
+            let elapsed = Instant::now().duration_since(self.last_edge).as_millis();
+
+            if is_down != self.down && elapsed >= DEBOUNCE_MS {
+                keyer.sidetone(is_down).await;
+                self.down = is_down;
+                self.last_edge = Instant::now();
+
+                if !is_down {
+                    return Event::Element(if elapsed >= DASH_THRESHOLD_MS { '-' } else { '.' });
+                }
+            } else if !self.down {
+                match keypad.scan() {
+                    Some('4') => return Event::Element('.'),
+                    Some('6') => return Event::Element('-'),
+                    Some('*') => return Event::Exit,
+                    _ => {}
+                }
+            }
+
+            Timer::after(Duration::from_millis(1)).await;
+        }
+    }
+}
+
+// Put a round result in the table, returns its rank if it made it in
+fn record_high_score(progress: &mut Progress, score: u16) -> Option<usize> {
+    let rank = progress.high_scores.iter().position(|s| score > *s)?;
+
+    for i in (rank + 1..HIGH_SCORES).rev() {
+        progress.high_scores[i] = progress.high_scores[i - 1];
+    }
+    progress.high_scores[rank] = score;
+
+    Some(rank)
+}
+
+async fn show_high_scores<D: Display>(lcd: &mut D, progress: &Progress) {
+    let mut table = String::<64>::new();
+
+    for (i, score) in progress.high_scores.iter().enumerate() {
+        if *score == 0 {
+            write!(table, "{}) --  ", i + 1).ok();
+        } else {
+            write!(table, "{}) {}.{}  ", i + 1, score / 10, score % 10).ok();
+        }
+    }
+
+    show_lines(lcd, "High scores WPM:", "");
+    TextBox::below(lcd, 1).speed(settings::get().scroll_speed()).play(lcd, &table).await;
+}
+
+// Play one round, returns its speed in tenths of WPM or None if the user left
+async fn play_round<D: Display>(
+    lcd: &mut D,
+    keypad: &mut Keypad,
+    keyer: &mut Keyer,
+    straight_key: &Input<'static>,
+    best_ms: &mut Option<u64>,
+) -> Option<u16> {
+    let mut rng = SmallRng::seed_from_u64(Instant::now().as_ticks());
+    let mut reader = ElementReader::new();
+    let mut total_ms = 0;
+    let mut total_units = 0;
+
+    for round in 0..ROUND {
+        let ch = LETTERS[rng.gen_range(0..LETTERS.len())];
+        let code = morse_table(ch).unwrap_or("");
+
+        let mut top = String::<16>::new();
+        write!(top, "{}/{}  Key: {}", round + 1, ROUND, ch).ok();
+        show_lines(lcd, &top, "");
+
+        let start = Instant::now();
+        let mut entered = String::<8>::new();
+
+        while entered != code {
+            let Event::Element(element) = reader.next(keypad, keyer, straight_key).await else {
+                keyer.sidetone(false).await;
+                return None;
+            };
+
+            entered.push(element).ok();
+            lcd.set_cursor_pos((0, 1));
+            lcd.write_str(&entered);
+
+            // A wrong element costs a restart of the character, the clock keeps running
+            if !code.starts_with(entered.as_str()) {
+                lcd.set_cursor_pos((0, 1));
+                lcd.write_str("Wrong!  ");
+                Timer::after(Duration::from_millis(300)).await;
+                lcd.set_cursor_pos((0, 1));
+                lcd.write_str("        ");
+                entered.clear();
+            }
+        }
+
+        let elapsed = Instant::now().duration_since(start).as_millis();
+        total_ms += elapsed;
+        // The character and the break after it
+        total_units += units(code) as u64 + 3;
+
+        let mut bottom = String::<16>::new();
+        if best_ms.is_none_or(|best| elapsed < best) {
+            *best_ms = Some(elapsed);
+            write!(bottom, "{} ms  Best!", elapsed).ok();
+        } else {
+            write!(bottom, "{} ms", elapsed).ok();
+        }
+        show_lines(lcd, &top, &bottom);
+        Timer::after(Duration::from_millis(700)).await;
+    }
+
+    Some((total_units * 12000 / total_ms.max(1)) as u16)
+}
+
+// Run the speed test until the user leaves it with '*'
+pub async fn run<D: Display>(
+    lcd: &mut D,
+    keypad: &mut Keypad,
+    keyer: &mut Keyer,
+    straight_key: &Input<'static>,
+    flash: &mut Storage,
+) {
+    let mut progress = storage::load(flash);
+    let mut best_ms = None;
+
+    loop {
+        show_lines(lcd, "Speed test  5=go", "#=scores *=exit");
+
+        match wait_key(keypad).await {
+            '5' => {}
+            '#' => {
+                show_high_scores(lcd, &progress).await;
+                wait_key(keypad).await;
+                continue;
+            }
+            '*' => return,
+            _ => continue,
+        }
+
+        let Some(score) = play_round(lcd, keypad, keyer, straight_key, &mut best_ms).await else {
+            continue;
+        };
+
+        defmt::info!("Speed test: {} tenths of WPM, best character {} ms", score, best_ms);
+
+        let mut top = String::<16>::new();
+        write!(top, "Round {}.{} WPM", score / 10, score % 10).ok();
+        let mut bottom = String::<16>::new();
+        match record_high_score(&mut progress, score) {
+            Some(rank) => {
+                storage::save(flash, &progress);
+                write!(bottom, "High score #{}!", rank + 1).ok();
+            }
+            None => {
+                write!(bottom, "Best char {}ms", best_ms.unwrap_or(0)).ok();
+            }
+        }
+
+        show_lines(lcd, &top, &bottom);
+        wait_key(keypad).await;
+    }
+}
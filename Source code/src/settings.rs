@@ -0,0 +1,772 @@
+//! User settings, and the menu that edits them.
+//!
+//! The settings live in RAM behind `get` and `set`, are saved in flash with the
+//! rest of the progress and read back at start. The menu is data: a tree of
+//! `Item`s where every setting names the kind of editor it needs (a number in a
+//! range, a choice, a toggle or a short text) with the functions that read and
+//! write its field, so a new setting only needs a field and an entry in `MENU`.
+//!
+//! '2' and '8' move through a menu, '5' opens the entry and '*' goes back up.
+//! In an editor '2' and '8' (or '6' and '4') change the value, which applies
+//! right away, '5' keeps it and '*' brings the old one back. Toggles flip as
+//! soon as they are opened.
+
+use core::cell::RefCell;
+use core::fmt::Write;
+
+use embassy_futures::select::{select, Either};
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_time::{Duration, Instant, Timer};
+use heapless::{String, Vec};
+
+use crate::display::{Cursor, Display};
+use crate::keyer::Keyer;
+use crate::keypad::Keypad;
+use crate::memories::MEMORIES;
+use crate::storage::{self, Storage};
+use crate::ui::{show_lines, show_menu, wait_key, wait_key_repeating};
+
+pub const TEXT_LEN: usize = 8;
+
+// Bytes taken by the settings in flash
+const SETTINGS_SIZE: usize = 26;
+
+// Version of the layout of the settings in flash, in their first byte
+const VERSION: u8 = 1;
+
+const MIN_WPM: u16 = 5;
+const MAX_WPM: u16 = 30;
+const MIN_MULTITAP_MS: u16 = 400;
+const MAX_MULTITAP_MS: u16 = 2000;
+const MIN_SCROLL_MS: u16 = 200;
+const MAX_SCROLL_MS: u16 = 1500;
+
+// The usual addresses of the PCF8574 and PCF8574A backpacks
+const LCD_ADDRESSES: [u8; 2] = [0x27, 0x3F];
+
+// Input modes in the order of the mode key
+const INPUT_MODES: &[&str] = &["ABC", "123", "T9", "?!@"];
+
+// Reports offered for {RST}
+const RST_REPORTS: &[&str] = &["599", "589", "579", "569", "559", "449", "339"];
+
+// Contest serial numbers run from 1 to this, then start over
+pub const MAX_SERIAL: u16 = 9999;
+
+// How digits are cut in serial numbers and reports, shortest last
+const CUT_NUMBERS: &[&str] = &["Off", "0 as T", "0 T, 9 N"];
+
+// Times between two beacon transmissions, in seconds, and their names
+pub const BEACON_INTERVALS: [u16; 9] = [15, 30, 60, 120, 300, 600, 900, 1800, 3600];
+const BEACON_INTERVAL_NAMES: &[&str] = &["15 s", "30 s", "1 min", "2 min", "5 min", "10 min", "15 min", "30 min", "60 min"];
+
+const MAX_CARRIER_S: u16 = 30;
+
+// Deepest nesting of menus
+const MENU_DEPTH: usize = 4;
+
+#[derive(Clone)]
+pub struct Settings {
+    // Sending speed, in words per minute
+    pub wpm: u8,
+    // Time without taps after which a multitap character is confirmed
+    pub multitap_ms: u16,
+    // Whether the buzzer sounds along with the LEDs
+    pub buzzer: bool,
+    // I2C address of the LCD, used from the next start
+    pub lcd_address: u8,
+    // Input mode the editor starts in, as presses of the mode key from ABC;
+    // the editor keeps it up to date with the mode last picked
+    pub start_mode: u8,
+    pub callsign: String<TEXT_LEN>,
+    // Index of the report in `RST_REPORTS`
+    pub rst: u8,
+    // Serial number sent for the next {NR}
+    pub serial: u16,
+    // Index of the style in `CUT_NUMBERS`
+    pub cut: u8,
+    // Memory the beacon sends, from 0
+    pub beacon_memory: u8,
+    // Index of the time between transmissions in `BEACON_INTERVALS`
+    pub beacon_interval: u8,
+    // Whether the beacon follows its message with DE and the callsign
+    pub beacon_id: bool,
+    // Seconds of steady tone after the message, 0 for none
+    pub beacon_carrier_s: u8,
+    // Time scrolling text stays on each position
+    pub scroll_ms: u16,
+}
+
+impl Settings {
+    pub const DEFAULT: Self = Self {
+        wpm: 6,
+        multitap_ms: 1000,
+        buzzer: true,
+        lcd_address: LCD_ADDRESSES[0],
+        start_mode: 0,
+        callsign: String::new(),
+        rst: 0,
+        serial: 1,
+        cut: 0,
+        beacon_memory: 0,
+        beacon_interval: 2,
+        beacon_id: true,
+        beacon_carrier_s: 0,
+        scroll_ms: 600,
+    };
+
+    // Length of a dot at the sending speed (PARIS standard)
+    pub fn unit_ms(&self) -> u64 {
+        1200 / self.wpm.max(1) as u64
+    }
+
+    pub fn multitap_timeout(&self) -> Duration {
+        Duration::from_millis(self.multitap_ms as u64)
+    }
+
+    pub fn scroll_speed(&self) -> Duration {
+        Duration::from_millis(self.scroll_ms as u64)
+    }
+
+    pub fn beacon_interval(&self) -> u16 {
+        BEACON_INTERVALS[self.beacon_interval as usize]
+    }
+
+    pub fn rst(&self) -> &'static str {
+        RST_REPORTS[self.rst as usize]
+    }
+
+    // A digit of a serial number or report as it is sent
+    pub fn cut_digit(&self, digit: char) -> char {
+        match (digit, self.cut) {
+            ('0', 1..) => 'T',
+            ('9', 2..) => 'N',
+            _ => digit,
+        }
+    }
+
+    // Layout in flash:
+    //   0      version
+    //   1      wpm
+    //   2..4   multitap delay, little endian
+    //   4      buzzer
+    //   5      LCD address
+    //   6      start mode
+    //   7      callsign length, then the callsign in 8..16
+    //   16     report
+    //   17..19 serial number, little endian
+    //   19     cut numbers
+    //   20..24 beacon memory, interval, CW ID and carrier
+    //   24..26 scroll speed, little endian
+    pub fn to_bytes(&self) -> [u8; SETTINGS_SIZE] {
+        let mut bytes = [0u8; SETTINGS_SIZE];
+
+        bytes[0] = VERSION;
+        bytes[1] = self.wpm;
+        bytes[2..4].copy_from_slice(&self.multitap_ms.to_le_bytes());
+        bytes[4] = self.buzzer as u8;
+        bytes[5] = self.lcd_address;
+        bytes[6] = self.start_mode;
+        bytes[7] = self.callsign.len() as u8;
+        bytes[8..8 + self.callsign.len()].copy_from_slice(self.callsign.as_bytes());
+        bytes[16] = self.rst;
+        bytes[17..19].copy_from_slice(&self.serial.to_le_bytes());
+        bytes[19] = self.cut;
+        bytes[20] = self.beacon_memory;
+        bytes[21] = self.beacon_interval;
+        bytes[22] = self.beacon_id as u8;
+        bytes[23] = self.beacon_carrier_s;
+        bytes[24..26].copy_from_slice(&self.scroll_ms.to_le_bytes());
+
+        bytes
+    }
+
+    // Settings read back from flash, None if they were saved in another
+    // layout or any of them is out of range
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let bytes: &[u8; SETTINGS_SIZE] = bytes.try_into().ok()?;
+        if bytes[0] != VERSION {
+            return None;
+        }
+
+        let multitap_ms = u16::from_le_bytes([bytes[2], bytes[3]]);
+        let serial = u16::from_le_bytes([bytes[17], bytes[18]]);
+        let scroll_ms = u16::from_le_bytes([bytes[24], bytes[25]]);
+        let text = bytes[8..16].get(..bytes[7] as usize)?;
+
+        let valid = (MIN_WPM..=MAX_WPM).contains(&(bytes[1] as u16))
+            && (MIN_MULTITAP_MS..=MAX_MULTITAP_MS).contains(&multitap_ms)
+            && bytes[4] <= 1
+            && LCD_ADDRESSES.contains(&bytes[5])
+            && (bytes[6] as usize) < INPUT_MODES.len()
+            && text.iter().all(|byte| text_chars_contain(*byte as char))
+            && (bytes[16] as usize) < RST_REPORTS.len()
+            && (1..=MAX_SERIAL).contains(&serial)
+            && (bytes[19] as usize) < CUT_NUMBERS.len()
+            && (bytes[20] as usize) < MEMORIES
+            && (bytes[21] as usize) < BEACON_INTERVALS.len()
+            && bytes[22] <= 1
+            && bytes[23] as u16 <= MAX_CARRIER_S
+            && (MIN_SCROLL_MS..=MAX_SCROLL_MS).contains(&scroll_ms);
+        if !valid {
+            return None;
+        }
+
+        let mut callsign = String::new();
+        for byte in text {
+            callsign.push(*byte as char).ok()?;
+        }
+
+        Some(Self {
+            wpm: bytes[1],
+            multitap_ms,
+            buzzer: bytes[4] == 1,
+            lcd_address: bytes[5],
+            start_mode: bytes[6],
+            callsign,
+            rst: bytes[16],
+            serial,
+            cut: bytes[19],
+            beacon_memory: bytes[20],
+            beacon_interval: bytes[21],
+            beacon_id: bytes[22] == 1,
+            beacon_carrier_s: bytes[23],
+            scroll_ms,
+        })
+    }
+}
+
+static SETTINGS: Mutex<CriticalSectionRawMutex, RefCell<Settings>> = Mutex::new(RefCell::new(Settings::DEFAULT));
+
+pub fn get() -> Settings {
+    SETTINGS.lock(|settings| settings.borrow().clone())
+}
+
+pub fn set(settings: Settings) {
+    SETTINGS.lock(|active| *active.borrow_mut() = settings);
+}
+
+// Make `settings` the active ones and keep them in flash
+pub fn save(flash: &mut Storage, settings: Settings) {
+    set(settings.clone());
+    let mut progress = storage::load(flash);
+    progress.settings = settings;
+    storage::save(flash, &progress);
+}
+
+// Hand the settings that the keyer follows over to it
+pub async fn apply(keyer: &mut Keyer) {
+    let settings = get();
+    keyer.set_unit(settings.unit_ms()).await;
+    keyer.set_sound(settings.buzzer).await;
+}
+
+pub enum Kind {
+    // A number from `min` to `max` in steps of `step`, followed by `unit`
+    Range {
+        min: u16,
+        max: u16,
+        step: u16,
+        unit: &'static str,
+        get: fn(&Settings) -> u16,
+        set: fn(&mut Settings, u16),
+    },
+    // One of `names`
+    Choice {
+        names: &'static [&'static str],
+        get: fn(&Settings) -> usize,
+        set: fn(&mut Settings, usize),
+    },
+    Toggle {
+        get: fn(&Settings) -> bool,
+        set: fn(&mut Settings, bool),
+    },
+    // Up to `TEXT_LEN` letters, digits and '/'
+    Text {
+        get: fn(&Settings) -> String<TEXT_LEN>,
+        set: fn(&mut Settings, String<TEXT_LEN>),
+    },
+}
+
+pub struct Setting {
+    name: &'static str,
+    kind: Kind,
+    // Only taken into account at the next start
+    restart: bool,
+}
+
+pub enum Item {
+    Menu(&'static str, &'static [Item]),
+    Setting(Setting),
+}
+
+impl Item {
+    fn name(&self) -> &'static str {
+        match self {
+            Item::Menu(name, _) => *name,
+            Item::Setting(setting) => setting.name,
+        }
+    }
+}
+
+const MENU: &[Item] = &[
+    Item::Menu(
+        "Keyer",
+        &[
+            Item::Setting(Setting {
+                name: "Speed",
+                kind: Kind::Range {
+                    min: MIN_WPM,
+                    max: MAX_WPM,
+                    step: 1,
+                    unit: "wpm",
+                    get: |settings| settings.wpm as u16,
+                    set: |settings, wpm| settings.wpm = wpm as u8,
+                },
+                restart: false,
+            }),
+            Item::Setting(Setting {
+                name: "Buzzer",
+                kind: Kind::Toggle {
+                    get: |settings| settings.buzzer,
+                    set: |settings, on| settings.buzzer = on,
+                },
+                restart: false,
+            }),
+        ],
+    ),
+    Item::Menu(
+        "Keypad",
+        &[
+            Item::Setting(Setting {
+                name: "Multitap delay",
+                kind: Kind::Range {
+                    min: MIN_MULTITAP_MS,
+                    max: MAX_MULTITAP_MS,
+                    step: 100,
+                    unit: "ms",
+                    get: |settings| settings.multitap_ms,
+                    set: |settings, ms| settings.multitap_ms = ms,
+                },
+                restart: false,
+            }),
+            Item::Setting(Setting {
+                name: "Start mode",
+                kind: Kind::Choice {
+                    names: INPUT_MODES,
+                    get: |settings| settings.start_mode as usize,
+                    set: |settings, mode| settings.start_mode = mode as u8,
+                },
+                restart: false,
+            }),
+        ],
+    ),
+    Item::Menu(
+        "Display",
+        &[
+            Item::Setting(Setting {
+                name: "LCD address",
+                kind: Kind::Choice {
+                    names: &["0x27", "0x3F"],
+                    get: |settings| LCD_ADDRESSES.iter().position(|a| *a == settings.lcd_address).unwrap_or(0),
+                    set: |settings, index| settings.lcd_address = LCD_ADDRESSES[index],
+                },
+                restart: true,
+            }),
+            Item::Setting(Setting {
+                name: "Scroll speed",
+                kind: Kind::Range {
+                    min: MIN_SCROLL_MS,
+                    max: MAX_SCROLL_MS,
+                    step: 100,
+                    unit: "ms",
+                    get: |settings| settings.scroll_ms,
+                    set: |settings, ms| settings.scroll_ms = ms,
+                },
+                restart: false,
+            }),
+        ],
+    ),
+    Item::Menu(
+        "Station",
+        &[
+            Item::Setting(Setting {
+                name: "Callsign",
+                kind: Kind::Text {
+                    get: |settings| settings.callsign.clone(),
+                    set: |settings, callsign| settings.callsign = callsign,
+                },
+                restart: false,
+            }),
+            Item::Setting(Setting {
+                name: "RST report",
+                kind: Kind::Choice {
+                    names: RST_REPORTS,
+                    get: |settings| settings.rst as usize,
+                    set: |settings, rst| settings.rst = rst as u8,
+                },
+                restart: false,
+            }),
+        ],
+    ),
+    Item::Menu(
+        "Contest",
+        &[
+            Item::Setting(Setting {
+                name: "Serial number",
+                kind: Kind::Range {
+                    min: 1,
+                    max: MAX_SERIAL,
+                    step: 1,
+                    unit: "",
+                    get: |settings| settings.serial,
+                    set: |settings, serial| settings.serial = serial,
+                },
+                restart: false,
+            }),
+            Item::Setting(Setting {
+                name: "Cut numbers",
+                kind: Kind::Choice {
+                    names: CUT_NUMBERS,
+                    get: |settings| settings.cut as usize,
+                    set: |settings, cut| settings.cut = cut as u8,
+                },
+                restart: false,
+            }),
+        ],
+    ),
+    Item::Menu(
+        "Beacon",
+        &[
+            Item::Setting(Setting {
+                name: "Message",
+                kind: Kind::Choice {
+                    names: &["M1", "M2", "M3", "M4", "M5", "M6", "M7", "M8"],
+                    get: |settings| settings.beacon_memory as usize,
+                    set: |settings, memory| settings.beacon_memory = memory as u8,
+                },
+                restart: false,
+            }),
+            Item::Setting(Setting {
+                name: "Interval",
+                kind: Kind::Choice {
+                    names: BEACON_INTERVAL_NAMES,
+                    get: |settings| settings.beacon_interval as usize,
+                    set: |settings, interval| settings.beacon_interval = interval as u8,
+                },
+                restart: false,
+            }),
+            Item::Setting(Setting {
+                name: "CW ID",
+                kind: Kind::Toggle {
+                    get: |settings| settings.beacon_id,
+                    set: |settings, on| settings.beacon_id = on,
+                },
+                restart: false,
+            }),
+            Item::Setting(Setting {
+                name: "Carrier",
+                kind: Kind::Range {
+                    min: 0,
+                    max: MAX_CARRIER_S,
+                    step: 5,
+                    unit: "s",
+                    get: |settings| settings.beacon_carrier_s as u16,
+                    set: |settings, seconds| settings.beacon_carrier_s = seconds as u8,
+                },
+                restart: false,
+            }),
+        ],
+    ),
+];
+
+// What each key types in a text: its letters, then its digit
+fn text_chars(key: char) -> Option<&'static [char]> {
+    match key {
+        '1' => Some(&['1', '/']),
+        '2' => Some(&['A', 'B', 'C', '2']),
+        '3' => Some(&['D', 'E', 'F', '3']),
+        '4' => Some(&['G', 'H', 'I', '4']),
+        '5' => Some(&['J', 'K', 'L', '5']),
+        '6' => Some(&['M', 'N', 'O', '6']),
+        '7' => Some(&['P', 'Q', 'R', 'S', '7']),
+        '8' => Some(&['T', 'U', 'V', '8']),
+        '9' => Some(&['W', 'X', 'Y', 'Z', '9']),
+        '0' => Some(&['0']),
+        _ => None,
+    }
+}
+
+fn text_chars_contain(c: char) -> bool {
+    "1234567890".chars().any(|key| text_chars(key).is_some_and(|chars| chars.contains(&c)))
+}
+
+// Type a text with multitap, starting from `text`: '*' drops the character
+// being tapped or deletes the last one, '#' keeps the text. None if the user
+// gave up with '*' on an empty text.
+async fn edit_text<D: Display>(
+    lcd: &mut D,
+    keypad: &mut Keypad,
+    name: &str,
+    mut text: String<TEXT_LEN>,
+) -> Option<String<TEXT_LEN>> {
+    let timeout = get().multitap_timeout();
+    // The key being tapped and how many times
+    let mut pending: Option<(char, usize)> = None;
+    let mut last_tap = Instant::now();
+
+    let candidate = |(key, taps): (char, usize)| text_chars(key).map(|chars| chars[taps % chars.len()]);
+
+    loop {
+        show_lines(lcd, name, &text);
+        let col = text.len() as u8;
+        match pending.and_then(candidate) {
+            Some(c) => {
+                lcd.set_cursor_pos((col, 1));
+                lcd.write_char(c);
+                lcd.set_cursor_pos((col, 1));
+                lcd.set_cursor(Cursor::Blink);
+            }
+            None => {
+                lcd.set_cursor_pos((col, 1));
+                lcd.set_cursor(Cursor::Underline);
+            }
+        }
+
+        // A key, or None once the pending character timed out
+        let key = match pending {
+            Some(_) => match select(wait_key(keypad), Timer::at(last_tap + timeout)).await {
+                Either::First(key) => Some(key),
+                Either::Second(_) => None,
+            },
+            None => Some(wait_key(keypad).await),
+        };
+
+        // Anything but another tap on the same key or '*' confirms the pending character
+        if key != Some('*') && key != pending.map(|(k, _)| k) {
+            if let Some(c) = pending.take().and_then(candidate) {
+                text.push(c).ok();
+                if key == Some('#') {
+                    continue;
+                }
+            }
+        }
+
+        match key {
+            Some('#') => {
+                lcd.set_cursor(Cursor::Hidden);
+                return Some(text);
+            }
+            Some('*') if pending.take().is_some() => {}
+            Some('*') if text.is_empty() => {
+                lcd.set_cursor(Cursor::Hidden);
+                return None;
+            }
+            Some('*') => {
+                text.pop();
+            }
+            Some(key) if text_chars(key).is_some() => {
+                pending = Some(match pending {
+                    Some((k, taps)) if k == key => (k, taps + 1),
+                    _ => (key, 0),
+                });
+                last_tap = Instant::now();
+            }
+            _ => {}
+        }
+    }
+}
+
+// Edit one setting, applying it as it changes; returns the edited settings if
+// the user kept them
+async fn edit<D: Display>(
+    lcd: &mut D,
+    keypad: &mut Keypad,
+    keyer: &mut Keyer,
+    setting: &Setting,
+) -> Option<Settings> {
+    let mut edited = get();
+    let mut line = String::<16>::new();
+
+    match setting.kind {
+        Kind::Range { min, max, step, unit, get, set } => {
+            let mut value = get(&edited);
+            loop {
+                line.clear();
+                write!(line, "< {}", value).ok();
+                if !unit.is_empty() {
+                    write!(line, " {}", unit).ok();
+                }
+                line.push_str(" >").ok();
+                show_lines(lcd, setting.name, &line);
+
+                value = match wait_key_repeating(keypad, &['2', '4', '6', '8']).await {
+                    '2' | '6' => (value + step).min(max),
+                    '8' | '4' => value.saturating_sub(step).max(min),
+                    '5' => return Some(edited),
+                    '*' => return None,
+                    _ => continue,
+                };
+
+                set(&mut edited, value);
+                self::set(edited.clone());
+                apply(keyer).await;
+            }
+        }
+        Kind::Choice { names, get, set } => {
+            let mut index = get(&edited);
+            loop {
+                line.clear();
+                write!(line, "< {} >", names[index]).ok();
+                show_lines(lcd, setting.name, &line);
+
+                index = match wait_key_repeating(keypad, &['2', '4', '6', '8']).await {
+                    '2' | '6' => (index + 1) % names.len(),
+                    '8' | '4' => (index + names.len() - 1) % names.len(),
+                    '5' => return Some(edited),
+                    '*' => return None,
+                    _ => continue,
+                };
+
+                set(&mut edited, index);
+                self::set(edited.clone());
+                apply(keyer).await;
+            }
+        }
+        Kind::Toggle { get, set } => {
+            let on = !get(&edited);
+            set(&mut edited, on);
+            show_lines(lcd, setting.name, if on { "On" } else { "Off" });
+            Timer::after(Duration::from_millis(800)).await;
+            Some(edited)
+        }
+        Kind::Text { get, set } => {
+            let text = edit_text(lcd, keypad, setting.name, get(&edited)).await?;
+            set(&mut edited, text);
+            Some(edited)
+        }
+    }
+}
+
+// Run the settings menu until the user leaves its top level with '*'
+pub async fn run<D: Display>(lcd: &mut D, keypad: &mut Keypad, keyer: &mut Keyer, flash: &mut Storage) {
+    // The menus above the current one, with the entry selected in each
+    let mut path: Vec<(&'static [Item], usize), MENU_DEPTH> = Vec::new();
+    let mut items = MENU;
+    let mut selected = 0;
+
+    loop {
+        show_menu(lcd, items.len(), |i| items[i].name(), selected);
+
+        match wait_key_repeating(keypad, &['2', '8']).await {
+            '2' => selected = (selected + items.len() - 1) % items.len(),
+            '8' => selected = (selected + 1) % items.len(),
+            '5' => match &items[selected] {
+                Item::Menu(_, entries) => {
+                    if path.push((items, selected)).is_ok() {
+                        items = *entries;
+                        selected = 0;
+                    }
+                }
+                Item::Setting(setting) => {
+                    let original = get();
+
+                    match edit(lcd, keypad, keyer, setting).await {
+                        Some(edited) => {
+                            save(flash, edited);
+
+                            defmt::info!("Setting '{}' changed", setting.name);
+                            if setting.restart {
+                                show_lines(lcd, "Saved, used at", "the next start");
+                                Timer::after(Duration::from_millis(1500)).await;
+                            }
+                        }
+                        None => set(original),
+                    }
+                    apply(keyer).await;
+                }
+            },
+            '*' => match path.pop() {
+                Some((parent, index)) => {
+                    items = parent;
+                    selected = index;
+                }
+                None => return,
+            },
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn custom() -> Settings {
+        let mut settings = Settings::DEFAULT;
+        settings.wpm = MAX_WPM as u8;
+        settings.multitap_ms = 1600;
+        settings.buzzer = false;
+        settings.lcd_address = LCD_ADDRESSES[1];
+        settings.start_mode = 2;
+        settings.callsign = String::try_from("EA3/F4AB").unwrap();
+        settings.rst = 3;
+        settings.serial = 1234;
+        settings.cut = 2;
+        settings.beacon_memory = 7;
+        settings.beacon_interval = 8;
+        settings.beacon_id = false;
+        settings.beacon_carrier_s = 10;
+        settings.scroll_ms = MIN_SCROLL_MS;
+        settings
+    }
+
+    #[test]
+    fn settings_round_trip() {
+        for settings in [Settings::DEFAULT, custom()] {
+            let bytes = settings.to_bytes();
+            let read = Settings::from_bytes(&bytes).unwrap();
+            assert_eq!(read.to_bytes(), bytes);
+        }
+
+        let read = Settings::from_bytes(&custom().to_bytes()).unwrap();
+        assert_eq!(read.callsign.as_str(), "EA3/F4AB");
+        assert_eq!(read.serial, 1234);
+        assert!(!read.buzzer && !read.beacon_id);
+    }
+
+    #[test]
+    fn other_layouts_are_refused() {
+        let bytes = Settings::DEFAULT.to_bytes();
+        assert!(Settings::from_bytes(&bytes[..SETTINGS_SIZE - 2]).is_none());
+        assert!(Settings::from_bytes(&[0; SETTINGS_SIZE + 1]).is_none());
+
+        let mut newer = bytes;
+        newer[0] = VERSION + 1;
+        assert!(Settings::from_bytes(&newer).is_none());
+    }
+
+    #[test]
+    fn out_of_range_settings_are_refused() {
+        let bytes = custom().to_bytes();
+        let corrupt = |index: usize, byte: u8| {
+            let mut bytes = bytes;
+            bytes[index] = byte;
+            Settings::from_bytes(&bytes)
+        };
+
+        assert!(corrupt(1, MAX_WPM as u8 + 1).is_none());
+        assert!(corrupt(5, 0x20).is_none());
+        assert!(corrupt(7, TEXT_LEN as u8 + 1).is_none());
+        assert!(corrupt(8, b'a').is_none());
+        assert!(corrupt(16, RST_REPORTS.len() as u8).is_none());
+        assert!(corrupt(20, MEMORIES as u8).is_none());
+        assert!(corrupt(21, BEACON_INTERVALS.len() as u8).is_none());
+        assert!(corrupt(23, MAX_CARRIER_S as u8 + 1).is_none());
+        // The serial number runs from 1
+        let mut bytes = bytes;
+        bytes[17..19].copy_from_slice(&0u16.to_le_bytes());
+        assert!(Settings::from_bytes(&bytes).is_none());
+    }
+}
@@ -0,0 +1,142 @@
+//! Morse "Simon says" memory game.
+//!
+//! The device plays a sequence of letters on the LEDs and the buzzer and the
+//! user types it back with multitap. Every round adds one letter and plays the
+//! sequence a little faster; a mistake costs one of the three lives.
+
+use core::fmt::Write;
+
+use embassy_time::{Duration, Instant, Timer};
+use heapless::{String, Vec};
+use rand::rngs::SmallRng;
+use rand::{Rng, SeedableRng};
+
+use crate::display::Display;
+use crate::keyer::Keyer;
+use crate::keypad::Keypad;
+use crate::settings;
+use crate::ui::{read_char, show_lines, wait_key};
+use crate::LETTERS;
+
+const LIVES: usize = 3;
+
+// Longest sequence, the user has won once it is repeated
+const MAX_SEQUENCE: usize = 32;
+
+// Every round shortens the dot by this much, down to the fastest speed
+const SPEEDUP_MS: u64 = 10;
+const FASTEST_UNIT_MS: u64 = 60;
+
+fn unit_for_round(round: usize) -> u64 {
+    settings::get()
+        .unit_ms()
+        .saturating_sub(SPEEDUP_MS * (round as u64 - 1))
+        .max(FASTEST_UNIT_MS)
+}
+
+// Let the user type the sequence back, returns the first wrong position
+// (and what was typed there), or None once everything matched
+async fn repeat_sequence<D: Display>(
+    lcd: &mut D,
+    keypad: &mut Keypad,
+    sequence: &[char],
+) -> Option<Option<(usize, char)>> {
+    show_lines(lcd, "Your turn:", "");
+
+    for (i, expected) in sequence.iter().enumerate() {
+        let pos = ((i % lcd.columns() as usize) as u8, 1);
+        let answer = read_char(lcd, keypad, pos).await?;
+
+        if answer != *expected {
+            return Some(Some((i, answer)));
+        }
+    }
+
+    Some(None)
+}
+
+// Play one game, returns the last round completed or None if the user left
+async fn play_game<D: Display>(
+    lcd: &mut D,
+    keypad: &mut Keypad,
+    keyer: &mut Keyer,
+) -> Option<usize> {
+    let mut rng = SmallRng::seed_from_u64(Instant::now().as_ticks());
+    let mut sequence = Vec::<char, MAX_SEQUENCE>::new();
+    let mut lives = LIVES;
+
+    loop {
+        // Only a sequence that was repeated correctly gets longer
+        if sequence.push(LETTERS[rng.gen_range(0..LETTERS.len())]).is_err() {
+            return Some(MAX_SEQUENCE);
+        }
+
+        loop {
+            let round = sequence.len();
+            let mut top = String::<16>::new();
+            write!(top, "Round {} Lives {}", round, lives).ok();
+            show_lines(lcd, &top, "Listen...");
+            Timer::after(Duration::from_millis(800)).await;
+
+            keyer.set_unit(unit_for_round(round)).await;
+            for ch in &sequence {
+                keyer.play_char(*ch).await;
+            }
+            settings::apply(keyer).await;
+
+            match repeat_sequence(lcd, keypad, &sequence).await? {
+                None => {
+                    show_lines(lcd, "Well done!", "");
+                    Timer::after(Duration::from_millis(800)).await;
+                    break;
+                }
+                Some((position, answer)) => {
+                    lives -= 1;
+
+                    let mut bottom = String::<16>::new();
+                    write!(bottom, "#{} was {} not {}", position + 1, sequence[position], answer).ok();
+                    show_lines(lcd, "Oops!", &bottom);
+                    Timer::after(Duration::from_millis(1500)).await;
+
+                    if lives == 0 {
+                        return Some(round - 1);
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Run the game until the user leaves it with '*'
+pub async fn run<D: Display>(lcd: &mut D, keypad: &mut Keypad, keyer: &mut Keyer) {
+    let mut best = 0;
+
+    loop {
+        show_lines(lcd, "Simon says  5=go", "*=exit");
+
+        match wait_key(keypad).await {
+            '5' => {}
+            '*' => return,
+            _ => continue,
+        }
+
+        let Some(rounds) = play_game(lcd, keypad, keyer).await else {
+            settings::apply(keyer).await;
+            continue;
+        };
+
+        let mut top = String::<16>::new();
+        write!(top, "Game over: {}", rounds).ok();
+        let mut bottom = String::<16>::new();
+        if rounds > best {
+            best = rounds;
+            write!(bottom, "New best!").ok();
+        } else {
+            write!(bottom, "Best: {}", best).ok();
+        }
+
+        defmt::info!("Simon says: {} rounds, best {}", rounds, best);
+        show_lines(lcd, &top, &bottom);
+        wait_key(keypad).await;
+    }
+}
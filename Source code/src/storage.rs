@@ -0,0 +1,490 @@
+//! Persistence of user data in on-board flash.
+//!
+//! The firmware is linked in the first 2 MiB of the flash (see `memory.x`), so
+//! the last sectors of the 4 MiB chip on the Pico 2W are free to hold data that
+//! must survive a power cycle.
+//!
+//! They hold a small key-value store. Every sector starts with a header (magic,
+//! version of the store and a sequence number) and the one with the highest
+//! sequence is active. Saving a value appends an entry (key, length, value,
+//! CRC-32) to the active sector, the last entry of a key wins. When the sector
+//! is full the latest values are copied to the next sector, whose header is
+//! written last, and the old one becomes free: the sectors are used in turn,
+//! so each is erased only once every `SECTORS` compactions. An entry with a
+//! bad CRC (power lost while it was written) ends the sector, and a value that
+//! is missing or does not check out falls back to its default.
+
+use embassy_rp::flash::{Blocking, Flash};
+use embassy_rp::peripherals::FLASH;
+use embedded_storage::nor_flash::NorFlash;
+use heapless::Vec;
+
+use crate::keymap::{self, Bindings, Layout};
+use crate::memories::{MEMORIES, MEMORY_SIZE, Memory};
+use crate::settings::Settings;
+
+pub const FLASH_SIZE: usize = 4 * 1024 * 1024;
+
+// Sectors at the end of the flash used by the store, in turn
+const SECTORS: usize = 4;
+
+// Marks a sector of the store
+const MAGIC: [u8; 4] = *b"MKVS";
+
+// Version of the store layout, for firmware that changes it
+const VERSION: u8 = 1;
+
+// Header layout:
+//   0..4   magic
+//   4      version
+//   8..12  sequence number, little endian
+//   12..16 CRC-32 of the bytes before
+const HEADER_SIZE: usize = 16;
+
+// Entry layout, padded to `ALIGN`:
+//   0      key, 0xFF for the free space after the last entry
+//   1      length of the value
+//   2..    value
+//   then   CRC-32 of the key, the length and the value
+const ENTRY_OVERHEAD: usize = 6;
+// The largest value is a message memory
+const MAX_VALUE: usize = MEMORY_SIZE;
+const ALIGN: usize = 4;
+
+const FREE: u8 = 0xFF;
+
+// Entries in the speed test high-score table
+pub const HIGH_SCORES: usize = 5;
+
+pub type Storage = Flash<'static, FLASH, Blocking, FLASH_SIZE>;
+
+#[derive(Copy, Clone, PartialEq)]
+enum Key {
+    Lesson = 1,
+    HighScores = 2,
+    Layout = 3,
+    Settings = 4,
+    Memory1 = 5,
+    Memory2 = 6,
+    Memory3 = 7,
+    Memory4 = 8,
+    Memory5 = 9,
+    Memory6 = 10,
+    Memory7 = 11,
+    Memory8 = 12,
+    Bindings = 13,
+}
+
+const KEYS: [Key; 5 + MEMORIES] = [
+    Key::Lesson,
+    Key::HighScores,
+    Key::Layout,
+    Key::Settings,
+    Key::Memory1,
+    Key::Memory2,
+    Key::Memory3,
+    Key::Memory4,
+    Key::Memory5,
+    Key::Memory6,
+    Key::Memory7,
+    Key::Memory8,
+    Key::Bindings,
+];
+
+impl Key {
+    fn from_u8(byte: u8) -> Option<Self> {
+        KEYS.iter().copied().find(|key| *key as u8 == byte)
+    }
+
+    fn index(self) -> usize {
+        self as usize - 1
+    }
+
+    // The key of message memory `index`, from 0
+    fn memory(index: usize) -> Self {
+        KEYS[Key::Memory1.index() + index]
+    }
+}
+
+type Value = Vec<u8, MAX_VALUE>;
+
+// The latest value of every key, None for the keys never saved
+type Values = [Option<Value>; KEYS.len()];
+
+pub struct Progress {
+    // Index of the highest tutorial lesson the user has unlocked
+    pub lesson: usize,
+    // Best speed test results in tenths of WPM, highest first, 0 for unused
+    pub high_scores: [u16; HIGH_SCORES],
+    // Logical key at each position of the keypad matrix
+    pub layout: Layout,
+    pub settings: Settings,
+    pub memories: [Memory; MEMORIES],
+    // Keys of the mode switch, the function layer and the commands
+    pub bindings: Bindings,
+}
+
+impl Progress {
+    const DEFAULT: Self = Self {
+        lesson: 0,
+        high_scores: [0; HIGH_SCORES],
+        layout: keymap::OVERLAYS[0].1,
+        settings: Settings::DEFAULT,
+        memories: [Memory::EMPTY; MEMORIES],
+        bindings: Bindings::DEFAULT,
+    };
+
+    fn from_values(values: &Values) -> Self {
+        let mut progress = Self::DEFAULT;
+        let value = |key: Key| values[key.index()].as_deref();
+
+        if let Some(&[lesson]) = value(Key::Lesson) {
+            progress.lesson = lesson as usize;
+        }
+
+        if let Some(scores) = value(Key::HighScores).filter(|bytes| bytes.len() == 2 * HIGH_SCORES) {
+            for (score, bytes) in progress.high_scores.iter_mut().zip(scores.chunks(2)) {
+                *score = u16::from_le_bytes([bytes[0], bytes[1]]);
+            }
+        }
+
+        if let Some(keys) = value(Key::Layout).filter(|bytes| bytes.len() == 16) {
+            let mut layout = [[keymap::NO_KEY; 4]; 4];
+            for (key, byte) in layout.iter_mut().flatten().zip(keys) {
+                *key = *byte as char;
+            }
+
+            if keymap::is_valid(&layout) {
+                progress.layout = layout;
+            }
+        }
+
+        if let Some(settings) = value(Key::Settings).and_then(Settings::from_bytes) {
+            progress.settings = settings;
+        }
+
+        for (index, memory) in progress.memories.iter_mut().enumerate() {
+            if let Some(saved) = value(Key::memory(index)).and_then(Memory::from_bytes) {
+                *memory = saved;
+            }
+        }
+
+        if let Some(keys) = value(Key::Bindings).filter(|bytes| bytes.len() == 2 * keymap::ACTIONS.len()) {
+            let mut bindings = Bindings::DEFAULT;
+            let all = bindings.keys.iter_mut().chain(bindings.function_keys.iter_mut());
+            for (key, byte) in all.zip(keys) {
+                *key = *byte as char;
+            }
+
+            if bindings.is_valid() {
+                progress.bindings = bindings;
+            }
+        }
+
+        // Keys of the menus the layout does not have would lock the user out
+        if !progress.bindings.fit(&progress.layout) {
+            progress.bindings = Bindings::DEFAULT;
+        }
+
+        progress
+    }
+
+    fn to_values(&self) -> Values {
+        let mut values: Values = Default::default();
+        let mut set = |key: Key, bytes: &[u8]| values[key.index()] = Vec::from_slice(bytes).ok();
+
+        set(Key::Lesson, &[self.lesson as u8]);
+
+        let mut scores = [0u8; 2 * HIGH_SCORES];
+        for (bytes, score) in scores.chunks_mut(2).zip(self.high_scores) {
+            bytes.copy_from_slice(&score.to_le_bytes());
+        }
+        set(Key::HighScores, &scores);
+
+        let mut keys = [0u8; 16];
+        for (byte, key) in keys.iter_mut().zip(self.layout.iter().flatten()) {
+            *byte = *key as u8;
+        }
+        set(Key::Layout, &keys);
+
+        set(Key::Settings, &self.settings.to_bytes());
+
+        for (index, memory) in self.memories.iter().enumerate() {
+            let (bytes, len) = memory.to_bytes();
+            set(Key::memory(index), &bytes[..len]);
+        }
+
+        let mut keys = [0u8; 2 * keymap::ACTIONS.len()];
+        let all = self.bindings.keys.iter().chain(self.bindings.function_keys.iter());
+        for (byte, key) in keys.iter_mut().zip(all) {
+            *byte = *key as u8;
+        }
+        set(Key::Bindings, &keys);
+
+        values
+    }
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!0u32, |crc, byte| {
+        (0..8).fold(crc ^ *byte as u32, |crc, _| {
+            if crc & 1 == 1 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 }
+        })
+    })
+}
+
+fn entry_size(len: usize) -> usize {
+    (ENTRY_OVERHEAD + len).next_multiple_of(ALIGN)
+}
+
+// Where the store starts, and the size of each of its sectors
+fn region<F: NorFlash>(flash: &F) -> (u32, usize) {
+    (flash.capacity() as u32 - (SECTORS * F::ERASE_SIZE) as u32, F::ERASE_SIZE)
+}
+
+fn sector_offset<F: NorFlash>(flash: &F, sector: usize) -> u32 {
+    let (start, size) = region(flash);
+    start + (sector * size) as u32
+}
+
+// An active sector, as found when it was read
+struct Sector {
+    index: usize,
+    sequence: u32,
+    // Offset of the free space in the sector, None if an entry was damaged
+    // and nothing more can be appended
+    end: Option<usize>,
+}
+
+// The sequence number and version in the header of `sector`, None if it is not
+// a sector of the store
+fn read_header<F: NorFlash>(flash: &mut F, sector: usize) -> Option<(u32, u8)> {
+    let mut header = [0u8; HEADER_SIZE];
+    let offset = sector_offset(flash, sector);
+    flash.read(offset, &mut header).ok()?;
+
+    let crc = u32::from_le_bytes([header[12], header[13], header[14], header[15]]);
+    if header[..4] != MAGIC || crc32(&header[..12]) != crc {
+        return None;
+    }
+
+    Some((u32::from_le_bytes([header[8], header[9], header[10], header[11]]), header[4]))
+}
+
+// Read the entries of `sector` into `values`, returns where its free space starts
+fn read_entries<F: NorFlash>(flash: &mut F, sector: usize, values: &mut Values) -> Option<usize> {
+    let base = sector_offset(flash, sector);
+    let size = F::ERASE_SIZE;
+    let mut pos = HEADER_SIZE;
+
+    while pos + ENTRY_OVERHEAD <= size {
+        let mut entry = [0u8; ENTRY_OVERHEAD + MAX_VALUE];
+        flash.read(base + pos as u32, &mut entry[..2]).ok()?;
+
+        if entry[0] == FREE {
+            return Some(pos);
+        }
+
+        let len = entry[1] as usize;
+        if len > MAX_VALUE || pos + entry_size(len) > size {
+            return None;
+        }
+
+        let total = ENTRY_OVERHEAD + len;
+        flash.read(base + pos as u32, &mut entry[..total]).ok()?;
+        let crc = u32::from_le_bytes([entry[total - 4], entry[total - 3], entry[total - 2], entry[total - 1]]);
+        if crc32(&entry[..total - 4]) != crc {
+            defmt::warn!("Damaged entry in sector {} at {}", sector, pos);
+            return None;
+        }
+
+        // Keys of newer firmware are skipped
+        if let Some(key) = Key::from_u8(entry[0]) {
+            values[key.index()] = Vec::from_slice(&entry[2..2 + len]).ok();
+        }
+        pos += entry_size(len);
+    }
+
+    Some(pos)
+}
+
+// The active sector and the values saved in it
+fn read_store<F: NorFlash>(flash: &mut F) -> (Option<Sector>, Values) {
+    let mut values: Values = Default::default();
+
+    let active = (0..SECTORS)
+        .filter_map(|index| read_header(flash, index).map(|(sequence, version)| (index, sequence, version)))
+        .max_by_key(|(_, sequence, _)| *sequence);
+
+    let Some((index, sequence, version)) = active else {
+        return (None, values);
+    };
+
+    if version != VERSION {
+        // Written by a newer firmware, whose values cannot be trusted to mean
+        // the same: start over from the defaults
+        defmt::warn!("Settings store version {} not supported", version);
+        return (Some(Sector { index, sequence, end: None }), values);
+    }
+
+    let end = read_entries(flash, index, &mut values);
+    (Some(Sector { index, sequence, end }), values)
+}
+
+// Append the entry of `key` at `pos` in `sector`
+fn write_entry<F: NorFlash>(flash: &mut F, sector: usize, pos: usize, key: Key, value: &[u8]) -> Result<(), F::Error> {
+    let mut entry = [FREE; ENTRY_OVERHEAD + MAX_VALUE + ALIGN];
+    let total = ENTRY_OVERHEAD + value.len();
+
+    entry[0] = key as u8;
+    entry[1] = value.len() as u8;
+    entry[2..2 + value.len()].copy_from_slice(value);
+    let crc = crc32(&entry[..total - 4]);
+    entry[total - 4..total].copy_from_slice(&crc.to_le_bytes());
+
+    let offset = sector_offset(flash, sector) + pos as u32;
+    flash.write(offset, &entry[..entry_size(value.len())])
+}
+
+// Write all of `values` to a fresh sector after `active`, which it replaces
+fn compact<F: NorFlash>(flash: &mut F, active: Option<&Sector>, values: &Values) -> Result<(), F::Error> {
+    let index = active.map_or(0, |sector| (sector.index + 1) % SECTORS);
+    let sequence = active.map_or(1, |sector| sector.sequence.wrapping_add(1));
+    let base = sector_offset(flash, index);
+
+    flash.erase(base, base + F::ERASE_SIZE as u32)?;
+
+    let mut pos = HEADER_SIZE;
+    for key in KEYS {
+        if let Some(value) = &values[key.index()] {
+            write_entry(flash, index, pos, key, value)?;
+            pos += entry_size(value.len());
+        }
+    }
+
+    // Last, so that the sector only counts once it holds every value
+    let mut header = [FREE; HEADER_SIZE];
+    header[..4].copy_from_slice(&MAGIC);
+    header[4] = VERSION;
+    header[8..12].copy_from_slice(&sequence.to_le_bytes());
+    let crc = crc32(&header[..12]);
+    header[12..16].copy_from_slice(&crc.to_le_bytes());
+    flash.write(base, &header)?;
+
+    defmt::info!("Settings store compacted into sector {} (sequence {})", index, sequence);
+    Ok(())
+}
+
+pub fn load<F: NorFlash>(flash: &mut F) -> Progress {
+    let (_, values) = read_store(flash);
+    Progress::from_values(&values)
+}
+
+pub fn save<F: NorFlash>(flash: &mut F, progress: &Progress) {
+    let (active, saved) = read_store(flash);
+    let values = progress.to_values();
+
+    let result = match active.as_ref().and_then(|sector| sector.end.map(|end| (sector, end))) {
+        Some((sector, mut end)) => {
+            let mut result = Ok(());
+
+            // Only the values that changed are appended
+            for key in KEYS {
+                let Some(value) = &values[key.index()] else { continue };
+                if saved[key.index()].as_ref() == Some(value) {
+                    continue;
+                }
+
+                if end + entry_size(value.len()) > F::ERASE_SIZE {
+                    result = compact(flash, Some(sector), &values);
+                    break;
+                }
+
+                result = write_entry(flash, sector.index, end, key, value);
+                if result.is_err() {
+                    break;
+                }
+                end += entry_size(value.len());
+            }
+            result
+        }
+        None => compact(flash, active.as_ref(), &values),
+    };
+
+    if result.is_err() {
+        defmt::warn!("Could not save the progress");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crc32_matches_the_standard_check_value() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn keys_round_trip() {
+        for key in KEYS {
+            assert!(Key::from_u8(key as u8) == Some(key));
+        }
+        assert!(Key::from_u8(0).is_none());
+        assert!(Key::from_u8(FREE).is_none());
+        assert!(Key::memory(MEMORIES - 1) == Key::Memory8);
+    }
+
+    #[test]
+    fn progress_round_trips_through_values() {
+        let mut progress = Progress::DEFAULT;
+        progress.lesson = 7;
+        progress.high_scores = [215, 180, 0, 0, 0];
+        progress.layout = keymap::OVERLAYS[1].1;
+        progress.settings.wpm = 18;
+        progress.settings.callsign = heapless::String::try_from("F4ABC").unwrap();
+        progress.memories[2].text = heapless::String::try_from("CQ DE {CALL}").unwrap();
+        progress.memories[2].repeat = 3;
+        progress.bindings.keys.swap(0, 2);
+
+        let read = Progress::from_values(&progress.to_values());
+
+        assert_eq!(read.lesson, 7);
+        assert_eq!(read.high_scores, progress.high_scores);
+        assert_eq!(read.layout, progress.layout);
+        assert_eq!(read.settings.to_bytes(), progress.settings.to_bytes());
+        assert_eq!(read.memories[2].text, progress.memories[2].text);
+        assert_eq!(read.memories[2].repeat, 3);
+        assert!(read.memories[0].is_empty());
+        assert!(read.bindings == progress.bindings);
+    }
+
+    #[test]
+    fn missing_or_bad_values_fall_back_to_the_defaults() {
+        let read = Progress::from_values(&Default::default());
+        assert_eq!(read.lesson, 0);
+        assert_eq!(read.layout, keymap::OVERLAYS[0].1);
+        assert!(read.bindings == Bindings::DEFAULT);
+
+        let mut values: Values = Default::default();
+        values[Key::HighScores.index()] = Vec::from_slice(&[1, 2, 3]).ok();
+        values[Key::Layout.index()] = Vec::from_slice(&[b'1'; 16]).ok();
+        values[Key::Settings.index()] = Vec::from_slice(&[VERSION + 1; 26]).ok();
+        let read = Progress::from_values(&values);
+        assert_eq!(read.high_scores, [0; HIGH_SCORES]);
+        assert_eq!(read.layout, keymap::OVERLAYS[0].1);
+        assert_eq!(read.settings.to_bytes(), Settings::DEFAULT.to_bytes());
+    }
+
+    #[test]
+    fn bindings_the_layout_cannot_reach_are_dropped() {
+        let mut progress = Progress::DEFAULT;
+        // The function layer moved to the HELLO key, which a phone keypad lacks
+        progress.layout = keymap::OVERLAYS[2].1;
+        progress.bindings.keys.swap(1, 3);
+
+        let read = Progress::from_values(&progress.to_values());
+        assert!(read.bindings == Bindings::DEFAULT);
+    }
+}
@@ -0,0 +1,177 @@
+//! Laying text out on the character grid of the screen.
+//!
+//! A `TextBox` is a rectangle of cells. Text put in it is wrapped at spaces
+//! (and at '\n'); a word longer than a line is cut where the line ends. Widths
+//! are counted in characters, never in bytes, so any UTF-8 text is safe. Text
+//! that does not fit is shown a page at a time or, in a box a single line
+//! high, scrolled through as a marquee, at the speed set for the box.
+
+use embassy_time::{Duration, Timer};
+
+use crate::display::Display;
+
+// Time a marquee stays on each position
+const DEFAULT_SPEED: Duration = Duration::from_millis(600);
+
+// A page stays this many marquee steps on screen for every line it holds
+const STEPS_PER_LINE: u32 = 4;
+
+// The lines of a text wrapped at `width` characters
+pub struct Lines<'a> {
+    rest: &'a str,
+    width: usize,
+}
+
+pub fn wrap(text: &str, width: usize) -> Lines<'_> {
+    Lines { rest: text, width: width.max(1) }
+}
+
+impl<'a> Iterator for Lines<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        let rest = self.rest.trim_start_matches(' ');
+        if rest.is_empty() {
+            return None;
+        }
+
+        // Byte offset after `width` characters, the most one line can take
+        let end = rest.char_indices().nth(self.width).map_or(rest.len(), |(i, _)| i);
+
+        let (line, next) = if let Some(newline) = rest[..end].find('\n') {
+            (&rest[..newline], &rest[newline + 1..])
+        } else if end == rest.len() || rest[end..].starts_with(' ') {
+            (&rest[..end], &rest[end..])
+        } else if rest[end..].starts_with('\n') {
+            // The line is full right before a newline, which is not another line
+            (&rest[..end], &rest[end + 1..])
+        } else {
+            match rest[..end].rfind(' ') {
+                Some(space) => (&rest[..space], &rest[space..]),
+                None => (&rest[..end], &rest[end..]),
+            }
+        };
+
+        self.rest = next;
+        Some(line.trim_end_matches(' '))
+    }
+}
+
+#[derive(Copy, Clone)]
+pub struct TextBox {
+    pos: (u8, u8),
+    columns: u8,
+    rows: u8,
+    speed: Duration,
+}
+
+impl TextBox {
+    pub fn new(pos: (u8, u8), (columns, rows): (u8, u8)) -> Self {
+        Self { pos, columns, rows, speed: DEFAULT_SPEED }
+    }
+
+    pub fn screen<D: Display>(lcd: &D) -> Self {
+        Self::new((0, 0), (lcd.columns(), lcd.rows()))
+    }
+
+    // The rows from `row` to the bottom of the screen
+    pub fn below<D: Display>(lcd: &D, row: u8) -> Self {
+        Self::new((0, row), (lcd.columns(), lcd.rows().saturating_sub(row).max(1)))
+    }
+
+    // Time a marquee stays on each position; pages stay a few times longer
+    pub fn speed(mut self, speed: Duration) -> Self {
+        self.speed = speed;
+        self
+    }
+
+    // Write `text` on `row` of the box, cut or padded to its width
+    pub fn write_line<D: Display>(&self, lcd: &mut D, row: u8, text: &str) {
+        lcd.set_cursor_pos((self.pos.0, self.pos.1 + row));
+        for c in text.chars().chain(core::iter::repeat(' ')).take(self.columns as usize) {
+            lcd.write_char(c);
+        }
+    }
+
+    pub fn pages(&self, text: &str) -> usize {
+        wrap(text, self.columns as usize).count().div_ceil(self.rows as usize).max(1)
+    }
+
+    // Draw page `page` of `text`, clearing the rest of the box
+    pub fn show_page<D: Display>(&self, lcd: &mut D, text: &str, page: usize) {
+        let mut lines = wrap(text, self.columns as usize).skip(page * self.rows as usize);
+
+        for row in 0..self.rows {
+            self.write_line(lcd, row, lines.next().unwrap_or(""));
+        }
+    }
+
+    // Scroll `text` once through the first line of the box, or just show it
+    // for a moment if it fits
+    pub async fn marquee<D: Display>(&self, lcd: &mut D, text: &str) {
+        let len = text.chars().count();
+        let width = self.columns as usize;
+
+        for i in 0..=len.saturating_sub(width) {
+            lcd.set_cursor_pos(self.pos);
+            for c in text.chars().skip(i).chain(core::iter::repeat(' ')).take(width) {
+                lcd.write_char(c);
+            }
+            Timer::after(self.speed).await;
+        }
+    }
+
+    // Show all of `text`: as a marquee in a box of one line, otherwise one
+    // page after the other, the last one staying on screen
+    pub async fn play<D: Display>(&self, lcd: &mut D, text: &str) {
+        if self.rows == 1 {
+            self.marquee(lcd, text).await;
+            return;
+        }
+
+        let pages = self.pages(text);
+        for page in 0..pages {
+            self.show_page(lcd, text, page);
+            if page + 1 < pages {
+                Timer::after(self.speed * STEPS_PER_LINE * self.rows as u32).await;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wrap_breaks_at_spaces() {
+        assert!(wrap("HELLO WORLD", 5).eq(["HELLO", "WORLD"]));
+        assert!(wrap("CQ CQ DE F4ABC", 8).eq(["CQ CQ DE", "F4ABC"]));
+        assert!(wrap("  SPACED   OUT  ", 6).eq(["SPACED", "OUT"]));
+    }
+
+    #[test]
+    fn wrap_cuts_words_longer_than_a_line() {
+        assert!(wrap("A VERY LONGWORD", 4).eq(["A", "VERY", "LONG", "WORD"]));
+    }
+
+    #[test]
+    fn wrap_breaks_at_newlines() {
+        assert!(wrap("AB\nCD", 10).eq(["AB", "CD"]));
+        assert!(wrap("A\n\nB", 10).eq(["A", "", "B"]));
+        // A newline right after a full line does not add an empty one
+        assert!(wrap("AB\nCD", 2).eq(["AB", "CD"]));
+    }
+
+    #[test]
+    fn wrap_counts_characters_not_bytes() {
+        assert!(wrap("ÉTÉ ÀÀ", 3).eq(["ÉTÉ", "ÀÀ"]));
+        assert!(wrap("ÉÉÉÉ", 2).eq(["ÉÉ", "ÉÉ"]));
+    }
+
+    #[test]
+    fn wrap_of_nothing_is_no_lines() {
+        assert_eq!(wrap("", 16).count(), 0);
+        assert_eq!(wrap("   ", 16).count(), 0);
+    }
+}
@@ -0,0 +1,201 @@
+//! SPI TFT backend of the display: an ST7789 or ILI9341 panel through
+//! `mipidsi`, with the text drawn by `embedded-graphics`.
+//!
+//! The text grid is laid out in 10x20 pixel cells. The panel has no cursor of
+//! its own, so an underline is drawn under the cursor cell instead; it does
+//! not blink. The Morse glyphs are drawn as shapes.
+
+use display_interface::WriteOnlyDataCommand;
+use embassy_time::Delay;
+use embedded_graphics::mono_font::ascii::FONT_10X20;
+use embedded_graphics::mono_font::MonoTextStyle;
+use embedded_graphics::pixelcolor::Rgb565;
+use embedded_graphics::prelude::*;
+use embedded_graphics::primitives::Rectangle;
+use embedded_graphics::text::{Baseline, Text};
+use embedded_hal_1::digital::OutputPin;
+use mipidsi::options::{ColorInversion, Orientation, Rotation};
+use mipidsi::Builder;
+
+use crate::display::{Cursor, Display, Glyph, Graphics};
+
+const CELL: Size = Size::new(10, 20);
+
+// Height of the cursor underline
+const CURSOR_HEIGHT: u32 = 2;
+
+const BACKGROUND: Rgb565 = Rgb565::BLACK;
+const FOREGROUND: Rgb565 = Rgb565::WHITE;
+const CURSOR_COLOR: Rgb565 = Rgb565::YELLOW;
+
+// Bring the panel up in landscape
+#[cfg(not(feature = "tft-ili9341"))]
+pub fn panel<DI, RST>(di: DI, rst: RST) -> impl DrawTarget<Color = Rgb565>
+where
+    DI: WriteOnlyDataCommand,
+    RST: OutputPin,
+{
+    let panel = Builder::new(mipidsi::models::ST7789, di)
+        .display_size(240, 320)
+        .reset_pin(rst)
+        .invert_colors(ColorInversion::Inverted)
+        .orientation(Orientation::new().rotate(Rotation::Deg90))
+        .init(&mut Delay);
+
+    match panel {
+        Ok(panel) => panel,
+        Err(_) => panic!("ST7789 did not initialize"),
+    }
+}
+
+#[cfg(feature = "tft-ili9341")]
+pub fn panel<DI, RST>(di: DI, rst: RST) -> impl DrawTarget<Color = Rgb565>
+where
+    DI: WriteOnlyDataCommand,
+    RST: OutputPin,
+{
+    let panel = Builder::new(mipidsi::models::ILI9341Rgb565, di)
+        .reset_pin(rst)
+        .orientation(Orientation::new().rotate(Rotation::Deg90))
+        .init(&mut Delay);
+
+    match panel {
+        Ok(panel) => panel,
+        Err(_) => panic!("ILI9341 did not initialize"),
+    }
+}
+
+pub struct Tft<T> {
+    target: T,
+    columns: u8,
+    rows: u8,
+    cursor_pos: (u8, u8),
+    cursor: Cursor,
+}
+
+impl<T: DrawTarget<Color = Rgb565>> Tft<T> {
+    pub fn new(target: T) -> Self {
+        let size = target.bounding_box().size;
+        let mut tft = Self {
+            target,
+            columns: (size.width / CELL.width) as u8,
+            rows: (size.height / CELL.height) as u8,
+            cursor_pos: (0, 0),
+            cursor: Cursor::Hidden,
+        };
+
+        tft.clear();
+        tft
+    }
+
+    fn cell_origin(&self, (col, row): (u8, u8)) -> Point {
+        Point::new((col as u32 * CELL.width) as i32, (row as u32 * CELL.height) as i32)
+    }
+
+    // The underline at the bottom of the cursor cell, in `color`
+    fn draw_cursor(&mut self, color: Rgb565) {
+        let origin = self.cell_origin(self.cursor_pos) + Point::new(0, (CELL.height - CURSOR_HEIGHT) as i32);
+        let area = Rectangle::new(origin, Size::new(CELL.width, CURSOR_HEIGHT));
+        self.target.fill_solid(&area, color).ok();
+    }
+
+    fn move_cursor(&mut self, pos: (u8, u8)) {
+        if self.cursor != Cursor::Hidden {
+            self.draw_cursor(BACKGROUND);
+        }
+
+        self.cursor_pos = pos;
+
+        if self.cursor != Cursor::Hidden {
+            self.draw_cursor(CURSOR_COLOR);
+        }
+    }
+}
+
+impl<T: DrawTarget<Color = Rgb565>> Display for Tft<T> {
+    fn columns(&self) -> u8 {
+        self.columns
+    }
+
+    fn rows(&self) -> u8 {
+        self.rows
+    }
+
+    fn clear(&mut self) {
+        self.target.clear(BACKGROUND).ok();
+        self.move_cursor((0, 0));
+    }
+
+    fn set_cursor_pos(&mut self, pos: (u8, u8)) {
+        self.move_cursor(pos);
+    }
+
+    fn write_char(&mut self, c: char) {
+        let (col, row) = self.cursor_pos;
+        if col >= self.columns || row >= self.rows {
+            return;
+        }
+
+        let origin = self.cell_origin(self.cursor_pos);
+        self.target.fill_solid(&Rectangle::new(origin, CELL), BACKGROUND).ok();
+
+        let mut text = [0u8; 4];
+        let style = MonoTextStyle::new(&FONT_10X20, FOREGROUND);
+        Text::with_baseline(c.encode_utf8(&mut text), origin, style, Baseline::Top)
+            .draw(&mut self.target)
+            .ok();
+
+        self.move_cursor((col + 1, row));
+    }
+
+    fn write_glyph(&mut self, glyph: Glyph) {
+        let (col, row) = self.cursor_pos;
+        if col >= self.columns || row >= self.rows {
+            return;
+        }
+
+        let origin = self.cell_origin(self.cursor_pos);
+        self.target.fill_solid(&Rectangle::new(origin, CELL), BACKGROUND).ok();
+
+        let middle = (CELL.height / 2) as i32 - 2;
+        let shape = match glyph {
+            Glyph::Dot | Glyph::OverDot => Rectangle::new(Point::new(3, middle), Size::new(4, 4)),
+            Glyph::Dash | Glyph::OverDash => Rectangle::new(Point::new(1, middle), Size::new(8, 4)),
+            Glyph::Bar(fifths) => {
+                Rectangle::new(Point::zero(), Size::new(CELL.width * fifths.min(5) as u32 / 5, CELL.height))
+            }
+        };
+        self.target.fill_solid(&shape.translate(origin), FOREGROUND).ok();
+
+        if matches!(glyph, Glyph::OverDot | Glyph::OverDash) {
+            let overbar = Rectangle::new(origin + Point::new(0, 2), Size::new(CELL.width, 2));
+            self.target.fill_solid(&overbar, FOREGROUND).ok();
+        }
+
+        self.move_cursor((col + 1, row));
+    }
+
+    fn set_cursor(&mut self, cursor: Cursor) {
+        if self.cursor != Cursor::Hidden {
+            self.draw_cursor(BACKGROUND);
+        }
+
+        self.cursor = cursor;
+        self.move_cursor(self.cursor_pos);
+    }
+
+    fn graphics(&mut self) -> Option<&mut dyn Graphics> {
+        Some(self)
+    }
+}
+
+impl<T: DrawTarget<Color = Rgb565>> Graphics for Tft<T> {
+    fn size(&self) -> (u32, u32) {
+        let size = self.target.bounding_box().size;
+        (size.width, size.height)
+    }
+
+    fn fill_rect(&mut self, area: Rectangle, color: Rgb565) {
+        self.target.fill_solid(&area, color).ok();
+    }
+}
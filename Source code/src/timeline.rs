@@ -0,0 +1,272 @@
+//! Keying timeline, for units with a TFT.
+//!
+//! The top of the screen scrolls the keyer output from right to left, one
+//! pixel column every `SAMPLE`, with every mark drawn as a bar, so the lengths
+//! of the dots, dashes and gaps can be seen and compared. A tick below the bars
+//! marks every second. Under them is the text the marks decode to, with the
+//! speed it was keyed at.
+//!
+//! The straight key (or the '0' key) keys by hand, '5' sends PARIS at the
+//! current speed as a reference and '*' leaves. The units have no audio input,
+//! so only what the unit keys itself is shown, there is no waterfall of a
+//! received signal.
+
+use core::fmt::Write;
+
+use embassy_rp::gpio::Input;
+use embassy_time::{Duration, Ticker};
+use embedded_graphics::pixelcolor::Rgb565;
+use embedded_graphics::prelude::*;
+use embedded_graphics::primitives::Rectangle;
+use heapless::{String, Vec};
+
+use crate::display::{Display, Graphics};
+use crate::fist::{self, Gap, KEYPAD_ELEMENT_KEY};
+use crate::keyer::Keyer;
+use crate::keypad::{KeyEvent, Keypad};
+use crate::morse::decode;
+use crate::settings;
+use crate::ui::{show_lines, wait_key};
+
+// Time per pixel column
+const SAMPLE_MS: u32 = 10;
+const SAMPLE: Duration = Duration::from_millis(SAMPLE_MS as u64);
+
+// Samples scrolled in between two redraws of the bars
+const FRAME_SAMPLES: u32 = 4;
+
+// Widest screen supported, in pixels
+const MAX_WIDTH: usize = 480;
+
+const TICK_MS: u32 = 1000;
+
+// Shortest dot the decoder follows
+const MIN_UNIT_MS: u32 = 20;
+
+const BACKGROUND: Rgb565 = Rgb565::BLACK;
+const MARK: Rgb565 = Rgb565::GREEN;
+const SCALE: Rgb565 = Rgb565::new(12, 24, 12);
+
+const REFERENCE: &str = "PARIS ";
+
+// The last screen width of samples, oldest first from `oldest`
+struct Timeline {
+    samples: [bool; MAX_WIDTH],
+    oldest: usize,
+    width: usize,
+    top: i32,
+    height: u32,
+}
+
+impl Timeline {
+    // An empty timeline in the band of `height` pixels from `top`, with its scale
+    fn new(graphics: &mut dyn Graphics, top: i32, height: u32) -> Self {
+        let width = (graphics.size().0 as usize).min(MAX_WIDTH);
+
+        graphics.fill_rect(Rectangle::new(Point::new(0, top), Size::new(width as u32, height)), BACKGROUND);
+        // Counted back from the right edge, where the samples come in
+        for x in (0..width).rev().step_by((TICK_MS / SAMPLE_MS) as usize) {
+            let tick = Rectangle::new(Point::new(x as i32, top + height as i32 + 2), Size::new(1, 6));
+            graphics.fill_rect(tick, SCALE);
+        }
+
+        Self { samples: [false; MAX_WIDTH], oldest: 0, width, top, height }
+    }
+
+    fn sample(&self, column: usize) -> bool {
+        self.samples[(self.oldest + column) % self.width]
+    }
+
+    fn push(&mut self, keyed: bool) {
+        self.samples[self.oldest] = keyed;
+        self.oldest = (self.oldest + 1) % self.width;
+    }
+
+    // Redraw the band, one rectangle per mark or space
+    fn draw(&self, graphics: &mut dyn Graphics) {
+        let mut start = 0;
+
+        while start < self.width {
+            let keyed = self.sample(start);
+            let end = (start..self.width)
+                .find(|column| self.sample(*column) != keyed)
+                .unwrap_or(self.width);
+
+            let area = Rectangle::new(
+                Point::new(start as i32, self.top),
+                Size::new((end - start) as u32, self.height),
+            );
+            graphics.fill_rect(area, if keyed { MARK } else { BACKGROUND });
+            start = end;
+        }
+    }
+}
+
+// Decodes the samples as they come, following the speed of the keying
+struct Decoder {
+    unit_ms: u32,
+    keyed: bool,
+    // Length of the current mark or space so far
+    run_ms: u32,
+    code: String<8>,
+    // A letter was decoded and no word break followed it yet
+    in_word: bool,
+    text: Vec<char, 32>,
+}
+
+impl Decoder {
+    fn new() -> Self {
+        Self {
+            unit_ms: settings::get().unit_ms() as u32,
+            keyed: false,
+            run_ms: 0,
+            code: String::new(),
+            in_word: false,
+            text: Vec::new(),
+        }
+    }
+
+    fn push_text(&mut self, c: char) {
+        if self.text.is_full() {
+            self.text.remove(0);
+        }
+        self.text.push(c).ok();
+    }
+
+    // Take one sample, returns true if the text changed
+    fn step(&mut self, keyed: bool) -> bool {
+        if keyed != self.keyed {
+            if self.keyed {
+                // A mark ended: a dot or a dash, and a new estimate of the unit
+                let (symbol, unit) = if self.run_ms < 2 * self.unit_ms {
+                    ('.', self.run_ms)
+                } else {
+                    ('-', self.run_ms / 3)
+                };
+                self.code.push(symbol).ok();
+                self.unit_ms = ((3 * self.unit_ms + unit) / 4).max(MIN_UNIT_MS);
+            }
+
+            self.keyed = keyed;
+            self.run_ms = 0;
+            return false;
+        }
+
+        self.run_ms += SAMPLE_MS;
+        if keyed {
+            return false;
+        }
+
+        let (gap, _) = fist::classify(self.run_ms, self.unit_ms);
+        if !self.code.is_empty() && gap != Gap::Element {
+            let c = decode(&self.code).unwrap_or('?');
+            self.code.clear();
+            self.push_text(c);
+            self.in_word = true;
+            true
+        } else if self.in_word && gap == Gap::Word {
+            self.push_text(' ');
+            self.in_word = false;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+// The decoded text on `row`, its end in view, and the speed under it
+fn show_text<D: Display>(lcd: &mut D, row: u8, decoder: &Decoder) {
+    let columns = lcd.columns() as usize;
+    let skip = decoder.text.len().saturating_sub(columns);
+
+    lcd.set_cursor_pos((0, row));
+    for c in decoder.text.iter().skip(skip).copied().chain(core::iter::repeat(' ')).take(columns) {
+        lcd.write_char(c);
+    }
+
+    let mut speed = String::<32>::new();
+    write!(speed, "{} wpm, dot {} ms", 1200 / decoder.unit_ms, decoder.unit_ms).ok();
+    lcd.set_cursor_pos((0, row + 1));
+    for c in speed.chars().chain(core::iter::repeat(' ')).take(columns) {
+        lcd.write_char(c);
+    }
+}
+
+// Run the timeline until the user leaves it with '*'
+pub async fn run<D: Display>(
+    lcd: &mut D,
+    keypad: &mut Keypad,
+    keyer: &mut Keyer,
+    straight_key: &Input<'static>,
+) {
+    if lcd.graphics().is_none() {
+        show_lines(lcd, "Timeline needs", "a TFT screen");
+        wait_key(keypad).await;
+        return;
+    }
+
+    lcd.clear();
+    lcd.set_cursor_pos((0, 0));
+    lcd.write_str("Keying timeline");
+    lcd.set_cursor_pos((0, lcd.rows() - 1));
+    lcd.write_str("0 key, 5 PARIS, * exit");
+
+    // The text on the two rows above the help line, the bars from half a row
+    // under the title to half a row above the text, leaving room for the ticks
+    let rows = lcd.rows();
+    let text_row = rows.saturating_sub(3).max(2);
+    let mut timeline = match lcd.graphics() {
+        Some(graphics) => {
+            let row_height = graphics.size().1 / rows as u32;
+            let bar_rows = (text_row - 2).max(1) as u32;
+            Timeline::new(graphics, (row_height + row_height / 2) as i32, bar_rows * row_height)
+        }
+        None => return,
+    };
+    let mut decoder = Decoder::new();
+    show_text(lcd, text_row, &decoder);
+
+    let mut hand_keyed = false;
+    let mut samples = 0;
+    let mut ticker = Ticker::every(SAMPLE);
+
+    loop {
+        let down = straight_key.is_low() || keypad.is_held(KEYPAD_ELEMENT_KEY);
+        if down != hand_keyed {
+            keyer.sidetone(down).await;
+            hand_keyed = down;
+        }
+
+        while let Some(event) = keypad.poll() {
+            match event {
+                KeyEvent::Tap('*') => {
+                    keyer.abort();
+                    keyer.sidetone(false).await;
+                    keypad.clear();
+                    return;
+                }
+                KeyEvent::Tap('5') => {
+                    for c in REFERENCE.chars() {
+                        keyer.queue_char(c).await;
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let keyed = keyer.is_keyed();
+        timeline.push(keyed);
+        if decoder.step(keyed) {
+            show_text(lcd, text_row, &decoder);
+        }
+
+        samples += 1;
+        if samples % FRAME_SAMPLES == 0 {
+            if let Some(graphics) = lcd.graphics() {
+                timeline.draw(graphics);
+            }
+        }
+
+        ticker.next().await;
+    }
+}
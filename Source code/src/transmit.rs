@@ -0,0 +1,125 @@
+//! Sending a text with the user in control of the transmission.
+//!
+//! While it plays, the fun facts key ('*') aborts it on the spot and the mode
+//! key ('#') pauses it (at the end of the current element) or resumes it,
+//! wherever the bindings put these keys.
+//!
+//! The top line shows the text, scrolling along with the transmission, with
+//! the cursor on the character being sent. Under it the code appears element
+//! by element as it is keyed: the whole text wrapped over the middle rows on
+//! screens that have them, on a 16x2 LCD a single line of it that moves on as
+//! it fills up. The bottom line shows the progress.
+
+use core::fmt::Write;
+use core::pin::pin;
+
+use embassy_futures::select::{select3, Either3};
+use heapless::String;
+
+use crate::display::{Cursor, Display};
+use crate::keyer::Keyer;
+use crate::keymap::{self, Binding, BACK};
+use crate::keypad::Keypad;
+use crate::text::TextBox;
+use crate::ui::{show_code, show_progress_bar};
+
+// Cells of the progress bar after the line of code, on screens without rows
+// for the whole code
+const BAR_CELLS: u8 = 4;
+
+// The current character is kept a 1/TEXT_LEAD of a line from the left edge,
+// so the text just sent stays in view
+const TEXT_LEAD: usize = 3;
+
+// The text on the top line, with the cursor left on the character at `index`
+fn show_text<D: Display>(lcd: &mut D, text: &str, index: usize, count: usize) {
+    let columns = lcd.columns() as usize;
+    let first = index.saturating_sub(columns / TEXT_LEAD).min(count.saturating_sub(columns));
+    let start = text.char_indices().nth(first).map_or(text.len(), |(i, _)| i);
+
+    TextBox::new((0, 0), (lcd.columns(), 1)).write_line(lcd, 0, &text[start..]);
+    lcd.set_cursor_pos(((index - first) as u8, 0));
+}
+
+fn show_progress<D: Display>(lcd: &mut D, text: &str, index: usize, count: usize, elements: usize) {
+    let row = lcd.rows() - 1;
+
+    if lcd.rows() >= 3 {
+        show_code(lcd, text, 1, (lcd.columns(), lcd.rows() - 2), index, elements);
+
+        let mut counter = String::<12>::new();
+        write!(counter, "{}/{} ", index + 1, count).ok();
+        lcd.set_cursor_pos((0, row));
+        lcd.write_str(&counter);
+        show_progress_bar(lcd, (counter.len() as u8, row), lcd.columns() - counter.len() as u8, index, count);
+    } else {
+        let code_cells = lcd.columns() - BAR_CELLS;
+        show_code(lcd, text, row, (code_cells, 1), index, elements);
+        show_progress_bar(lcd, (code_cells, row), BAR_CELLS, index, count);
+    }
+
+    // Last, so that the cursor ends up on the text
+    show_text(lcd, text, index, count);
+}
+
+// Play `text`, returns false if the user aborted it
+pub async fn send<D: Display>(lcd: &mut D, keypad: &mut Keypad, keyer: &mut Keyer, text: &str) -> bool {
+    let count = text.chars().count();
+
+    // What is still playing (the echo of the editor) ends first, and its
+    // elements must not show as those of the first character
+    keyer.flush().await;
+    keyer.clear_element();
+
+    lcd.clear();
+    lcd.set_cursor(Cursor::Blink);
+
+    for (index, ch) in text.chars().enumerate() {
+        let mut elements = 0;
+        show_progress(lcd, text, index, count, elements);
+        keyer.queue_char(ch).await;
+
+        // A single flush for the character, asked again it would still be
+        // queued once the character is played, and end the next one early
+        let mut flushed = pin!(keyer.flush());
+
+        // Follow the elements and watch the keys until the character has been played
+        loop {
+            let event = select3(keyer.element(), keypad.wait(), &mut flushed).await;
+
+            match event {
+                Either3::First(element) => {
+                    elements = element + 1;
+                    show_progress(lcd, text, index, count, elements);
+                    continue;
+                }
+                Either3::Third(_) => break,
+                Either3::Second(_) => {}
+            }
+
+            match keypad.scan().and_then(keymap::binding) {
+                Some(BACK) => {
+                    keyer.abort();
+                    lcd.set_cursor(Cursor::Hidden);
+                    defmt::info!("Transmission aborted at char {}/{}", index + 1, count);
+                    return false;
+                }
+                Some(Binding::ModeSwitch) if keyer.is_paused() => {
+                    keyer.resume();
+                    show_progress(lcd, text, index, count, elements);
+                }
+                Some(Binding::ModeSwitch) => {
+                    keyer.pause();
+                    let mut paused = String::<16>::new();
+                    write!(paused, "Paused  {}=resume", keymap::key_of(Binding::ModeSwitch)).ok();
+                    TextBox::new((0, lcd.rows() - 1), (lcd.columns(), 1)).write_line(lcd, 0, &paused);
+                    show_text(lcd, text, index, count);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    lcd.set_cursor(Cursor::Hidden);
+    true
+}
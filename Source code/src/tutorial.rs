@@ -0,0 +1,206 @@
+//! Guided Morse course, one small group of characters per lesson.
+//!
+//! Every lesson introduces its characters with their mnemonics, plays them and
+//! then tests the learner on them (mixed with the characters of the earlier
+//! lessons). Passing the test unlocks the next lesson; progress is kept in
+//! flash so it survives a power cycle.
+
+use core::fmt::Write;
+
+use embassy_time::{Duration, Instant, Timer};
+use heapless::String;
+use rand::rngs::SmallRng;
+use rand::{Rng, SeedableRng};
+
+use crate::display::Display;
+use crate::keyer::Keyer;
+use crate::keypad::Keypad;
+use crate::morse::mnemonic;
+use crate::settings;
+use crate::storage::{self, Storage};
+use crate::text::TextBox;
+use crate::ui::{read_char, show_lines, wait_key, write_code};
+
+pub struct Lesson {
+    pub chars: &'static str,
+    pub intro: &'static str,
+}
+
+pub const LESSONS: &[Lesson] = &[
+    Lesson { chars: "ET", intro: "The shortest signs: one dot, one dash." },
+    Lesson { chars: "AN", intro: "A and N mirror each other." },
+    Lesson { chars: "IM", intro: "Two dots, two dashes." },
+    Lesson { chars: "SO", intro: "Three dots, three dashes: SOS!" },
+    Lesson { chars: "DK", intro: "A dash, then dots and dashes." },
+    Lesson { chars: "RU", intro: "Three elements each." },
+    Lesson { chars: "GW", intro: "G and W mirror each other." },
+    Lesson { chars: "HV", intro: "Four elements, starting with dots." },
+    Lesson { chars: "FL", intro: "Dot pairs around one dash." },
+    Lesson { chars: "BP", intro: "B starts long, P starts short." },
+    Lesson { chars: "CJ", intro: "Two rhythms worth memorizing." },
+    Lesson { chars: "QX", intro: "Rare letters, still needed." },
+    Lesson { chars: "YZ", intro: "The last letters of the alphabet." },
+    Lesson { chars: "12345", intro: "Digits start with dots, then dashes." },
+    Lesson { chars: "67890", intro: "Then dashes, then dots." },
+];
+
+// Questions in a lesson test, and how many must be right to pass
+const QUESTIONS: usize = 10;
+const PASS_SCORE: usize = 8;
+
+fn lesson_title(index: usize) -> String<16> {
+    let mut title = String::new();
+    write!(title, "Lesson {}/{}", index + 1, LESSONS.len()).ok();
+    title
+}
+
+// Let the user pick one of the unlocked lessons
+async fn choose_lesson<D: Display>(
+    lcd: &mut D,
+    keypad: &mut Keypad,
+    unlocked: usize,
+) -> Option<usize> {
+    let mut selected = unlocked;
+
+    loop {
+        show_lines(lcd, &lesson_title(selected), LESSONS[selected].chars);
+
+        match wait_key(keypad).await {
+            '2' => selected = selected.saturating_sub(1),
+            '8' => selected = (selected + 1).min(unlocked),
+            '5' => return Some(selected),
+            '*' => return None,
+            _ => {}
+        }
+    }
+}
+
+// Present the characters of a lesson, returns false if the user left
+async fn introduce<D: Display>(
+    lcd: &mut D,
+    keypad: &mut Keypad,
+    keyer: &mut Keyer,
+    lesson: &Lesson,
+) -> bool {
+    show_lines(lcd, "New characters:", "");
+    TextBox::below(lcd, 1).speed(settings::get().scroll_speed()).play(lcd, lesson.intro).await;
+
+    for ch in lesson.chars.chars() {
+        show_lines(lcd, "", mnemonic(ch).unwrap_or(""));
+        lcd.set_cursor_pos((0, 0));
+        lcd.write_char(ch);
+        lcd.write_str("  ");
+        write_code(lcd, ch);
+
+        // '5' plays it again, any other key moves on
+        loop {
+            keyer.play_char(ch).await;
+            keyer.play_char(ch).await;
+
+            match wait_key(keypad).await {
+                '5' => continue,
+                '*' => return false,
+                _ => break,
+            }
+        }
+    }
+
+    true
+}
+
+// Test the learner, returns the score or None if the user left
+async fn test<D: Display>(
+    lcd: &mut D,
+    keypad: &mut Keypad,
+    keyer: &mut Keyer,
+    index: usize,
+) -> Option<usize> {
+    let mut rng = SmallRng::seed_from_u64(Instant::now().as_ticks());
+    let mut score = 0;
+
+    for question in 0..QUESTIONS {
+        // Half of the questions are on the new characters, the rest reviews
+        let pool = if index == 0 || rng.gen_bool(0.5) {
+            LESSONS[index].chars
+        } else {
+            LESSONS[rng.gen_range(0..index)].chars
+        };
+        let ch = pool.chars().nth(rng.gen_range(0..pool.len())).unwrap_or('E');
+
+        let mut top = String::<16>::new();
+        write!(top, "Q{}/{} Listen...", question + 1, QUESTIONS).ok();
+        show_lines(lcd, &top, "Answer:");
+        Timer::after(Duration::from_millis(500)).await;
+        keyer.play_char(ch).await;
+
+        // Digits are typed directly, letters with multitap
+        let answer = if ch.is_ascii_digit() {
+            match wait_key(keypad).await {
+                '*' => return None,
+                key => key,
+            }
+        } else {
+            read_char(lcd, keypad, (8, 1)).await?
+        };
+        if answer == ch {
+            score += 1;
+            show_lines(lcd, "Correct!", "");
+        } else {
+            let mut bottom = String::<16>::new();
+            write!(bottom, "It was {} ", ch).ok();
+            show_lines(lcd, "Not quite...", &bottom);
+            lcd.set_cursor_pos((bottom.len() as u8, 1));
+            write_code(lcd, ch);
+            keyer.play_char(ch).await;
+        }
+        Timer::after(Duration::from_millis(1000)).await;
+    }
+
+    Some(score)
+}
+
+// Run the tutorial until the user leaves it with '*'
+pub async fn run<D: Display>(
+    lcd: &mut D,
+    keypad: &mut Keypad,
+    keyer: &mut Keyer,
+    flash: &mut Storage,
+) {
+    let mut progress = storage::load(flash);
+    let mut unlocked = progress.lesson.min(LESSONS.len() - 1);
+
+    while let Some(index) = choose_lesson(lcd, keypad, unlocked).await {
+        let lesson = &LESSONS[index];
+
+        if !introduce(lcd, keypad, keyer, lesson).await {
+            continue;
+        }
+
+        show_lines(lcd, "Test time!", "");
+        let marquee = TextBox::below(lcd, 1).speed(settings::get().scroll_speed());
+        marquee.play(lcd, "Type what you hear, # confirms").await;
+
+        let Some(score) = test(lcd, keypad, keyer, index).await else {
+            continue;
+        };
+
+        let mut top = String::<16>::new();
+        write!(top, "Score {}/{}", score, QUESTIONS).ok();
+
+        if score < PASS_SCORE {
+            let mut bottom = String::<16>::new();
+            write!(bottom, "Need {} to pass", PASS_SCORE).ok();
+            show_lines(lcd, &top, &bottom);
+        } else if index == unlocked && unlocked + 1 < LESSONS.len() {
+            unlocked += 1;
+            progress.lesson = unlocked;
+            storage::save(flash, &progress);
+            defmt::info!("Tutorial: lesson {} unlocked", unlocked + 1);
+            show_lines(lcd, &top, "Next lesson open");
+        } else {
+            show_lines(lcd, &top, "Passed!");
+        }
+
+        wait_key(keypad).await;
+    }
+}
@@ -0,0 +1,204 @@
+//! Small screen and keypad helpers shared by the modes.
+
+use embassy_time::{Duration, Instant, Timer};
+
+use crate::display::{Display, Glyph};
+use crate::get_multitap_chars;
+use crate::keymap::{self, Binding, BACK};
+use crate::keypad::{KeyEvent, Keypad};
+use crate::morse::{morse_table, prosign};
+use crate::settings;
+use crate::text::TextBox;
+
+// Replace the whole screen with two lines of text, cut to its width
+pub fn show_lines<D: Display>(lcd: &mut D, top: &str, bottom: &str) {
+    let lines = TextBox::screen(lcd);
+    lcd.clear();
+    lines.write_line(lcd, 0, top);
+    lines.write_line(lcd, 1, bottom);
+}
+
+// A menu of `count` entries named by `name`, from the selected one (marked)
+// down, as many as there are rows; the list wraps around
+pub fn show_menu<'a, D: Display>(lcd: &mut D, count: usize, name: impl Fn(usize) -> &'a str, selected: usize) {
+    let entries = TextBox::new((1, 0), (lcd.columns() - 1, lcd.rows()));
+    lcd.clear();
+    lcd.set_cursor_pos((0, 0));
+    lcd.write_char('>');
+
+    for row in 0..(lcd.rows() as usize).min(count) {
+        entries.write_line(lcd, row as u8, name((selected + row) % count));
+    }
+}
+
+// Wait until a key is pressed and released
+pub async fn wait_key(keypad: &mut Keypad) -> char {
+    loop {
+        if let Some(key) = keypad.scan() {
+            return key;
+        }
+
+        keypad.wait().await;
+    }
+}
+
+// Like `wait_key`, but holding one of the `repeating` keys also returns it,
+// once when the hold is recognised and then at the repeat rate
+pub async fn wait_key_repeating(keypad: &mut Keypad, repeating: &[char]) -> char {
+    loop {
+        match keypad.poll() {
+            Some(KeyEvent::Tap(key)) => return key,
+            Some(KeyEvent::LongPress(key) | KeyEvent::Repeat(key)) if repeating.contains(&key) => return key,
+            Some(_) => {}
+            None => keypad.wait().await,
+        }
+    }
+}
+
+// Cells taken by the code of `c`; a space (or a character without a code)
+// stays one blank cell
+pub fn code_width(c: char) -> usize {
+    morse_table(c).map_or(1, str::len)
+}
+
+// Write the code of `c` in glyphs, under an overbar if it is a prosign
+pub fn write_code<D: Display>(lcd: &mut D, c: char) {
+    write_code_part(lcd, c, usize::MAX);
+}
+
+// Write only the first `elements` glyphs of the code of `c`, and blanks for
+// the rest of it
+pub fn write_code_part<D: Display>(lcd: &mut D, c: char, elements: usize) {
+    let Some(code) = morse_table(c) else {
+        lcd.write_char(' ');
+        return;
+    };
+
+    let overbar = prosign(c).is_some();
+    for (index, symbol) in code.chars().enumerate() {
+        if index >= elements {
+            lcd.write_char(' ');
+            continue;
+        }
+
+        lcd.write_glyph(match (symbol, overbar) {
+            ('.', false) => Glyph::Dot,
+            ('.', true) => Glyph::OverDot,
+            (_, false) => Glyph::Dash,
+            (_, true) => Glyph::OverDash,
+        });
+    }
+}
+
+// Where the code of each character of `text` goes when it is wrapped in lines
+// of `columns` cells, a character never split: (index, character, line, column)
+fn code_layout(text: &str, columns: usize) -> impl Iterator<Item = (usize, char, usize, usize)> + '_ {
+    text.chars().enumerate().scan((0, 0), move |(line, end), (index, c)| {
+        let width = code_width(c);
+        // One blank cell between two characters
+        let mut col = if *end == 0 { 0 } else { *end + 1 };
+        if col > 0 && col + width > columns {
+            *line += 1;
+            col = 0;
+        }
+
+        *end = col + width;
+        Some((index, c, *line, col))
+    })
+}
+
+// Show the code of `text` wrapped on `rows` rows of `columns` cells from
+// `first_row`, as far as it was sent: up to the character at `current`, of
+// which only `elements` are shown. It scrolls to keep that character on screen.
+pub fn show_code<D: Display>(
+    lcd: &mut D,
+    text: &str,
+    first_row: u8,
+    (columns, rows): (u8, u8),
+    current: usize,
+    elements: usize,
+) {
+    let columns = columns as usize;
+    let current_line = code_layout(text, columns)
+        .find(|(index, ..)| *index == current)
+        .map_or(0, |(_, _, line, _)| line);
+    let first_line = (current_line + 1).saturating_sub(rows as usize);
+
+    for row in first_row..first_row + rows {
+        lcd.set_cursor_pos((0, row));
+        for _ in 0..columns {
+            lcd.write_char(' ');
+        }
+    }
+
+    for (index, c, line, col) in code_layout(text, columns).take(current + 1) {
+        if (first_line..first_line + rows as usize).contains(&line) {
+            lcd.set_cursor_pos((col as u8, first_row + (line - first_line) as u8));
+            write_code_part(lcd, c, if index == current { elements } else { usize::MAX });
+        }
+    }
+}
+
+// A bar of `cells` cells from `pos`, filled to `done` out of `total`
+pub fn show_progress_bar<D: Display>(lcd: &mut D, pos: (u8, u8), cells: u8, done: usize, total: usize) {
+    let fifths = done * cells as usize * 5 / total.max(1);
+
+    lcd.set_cursor_pos(pos);
+    for cell in 0..cells as usize {
+        lcd.write_glyph(Glyph::Bar(fifths.saturating_sub(cell * 5).min(5) as u8));
+    }
+}
+
+// Read one letter with multitap, shown at `pos` while it is being chosen.
+// The letter is confirmed after a second without taps, or right away with the
+// mode key ('#'). Returns None if the user gives up with the fun facts key ('*').
+pub async fn read_char<D: Display>(lcd: &mut D, keypad: &mut Keypad, pos: (u8, u8)) -> Option<char> {
+    let timeout = settings::get().multitap_timeout();
+    let mut last_key: Option<char> = None;
+    let mut tap_index = 0;
+    let mut last_press_time = Instant::now();
+
+    // The letter the taps so far point at
+    let candidate = |key: char, tap_index: usize| {
+        get_multitap_chars(key).map(|chars| chars[tap_index % chars.len()])
+    };
+
+    loop {
+        if let Some(last) = last_key {
+            if Instant::now().duration_since(last_press_time) >= timeout {
+                return candidate(last, tap_index);
+            }
+        }
+
+        let key = keypad.scan();
+        match key.and_then(keymap::binding) {
+            Some(BACK) => return None,
+            Some(Binding::ModeSwitch) if last_key.is_some() => {
+                return last_key.and_then(|last| candidate(last, tap_index));
+            }
+            _ => {}
+        }
+
+        match key {
+            Some(key) if get_multitap_chars(key).is_some() => {
+                if let Some(last) = last_key {
+                    if last != key {
+                        return candidate(last, tap_index);
+                    }
+                    tap_index += 1;
+                }
+
+                last_key = Some(key);
+                last_press_time = Instant::now();
+                if let Some(ch) = candidate(key, tap_index) {
+                    lcd.set_cursor_pos(pos);
+                    lcd.write_char(ch);
+                }
+            }
+            Some(_) => {}
+            // A pending letter still has to time out
+            None if last_key.is_some() => Timer::after(Duration::from_millis(10)).await,
+            None => keypad.wait().await,
+        }
+    }
+}